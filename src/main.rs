@@ -3,76 +3,202 @@ use raymod::*;
 
 use rayon::prelude::*;
 
-use std::sync::Arc;
-use std::io::Write;
+const ASPECT_RATIO: f64 = 16.0 / 9.0;
+const MAX_DEPTH: i64 = 32;
 
-fn ray_color(r: &Ray,world:&dyn Shape,depth:i64) -> Vec3 {
-	if depth <= 0 {
-        return Vec3::new(0.0, 0.0, 0.0);
-    }
-    let hit_info=world.hit(&r,EPS,f64::MAX);
-    if let Some(hit)=hit_info {
-        let scatter_info = hit.m.scatter(r, &hit);
-        if let Some(scatter)=scatter_info {
-            scatter.albedo.mult(ray_color(&scatter.ray,world,depth-1) )
-        }else{
-            return Vec3::new(0.0,0.0,0.0)
+fn sky(r: &Ray) -> Color {
+    let t=0.5*(r.d.norm().y+1.0);
+    Vec3::new(1.0,1.0,1.0)*(1.0-t)+Vec3::new(0.5,0.7,1.0)*t
+}
+
+//--pixelで表示するパスの1反射
+enum Bounce<'a> {
+    Miss,
+    //散乱した先の方向と、そこまでの減衰
+    Scatter { hit: &'a HitInfo<'a>, dir: Vec3, throughput: Color },
+    Absorb { hit: &'a HitInfo<'a>, emitted: Color },
+}
+
+//反射ごとに呼ばれる表示用の関数
+type Trace<'a> = Option<&'a mut dyn FnMut(i64, Bounce)>;
+
+//1本のパスを追って放射輝度を返す、traceがあれば反射ごとに呼ぶ
+//スペクトルモードではRGBの値をレイの波長での値に直してから掛ける
+fn ray_color(r: &Ray, world: &dyn Shape, filter: &FireflyFilter, mut trace: Trace) -> Vec3 {
+    let mut ray = r.clone();
+    //最初の散乱の重みと、そこから先の減衰
+    let mut first = Vec3::new(1.0, 1.0, 1.0);
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    //2つ目の交点までに届いた光と、その先から届いた光
+    let mut radiance = Color::zero();
+    let mut indirect = Color::zero();
+    //パスが1度鏡面で反射・屈折していればtrue、その後の鏡面は正則化の対象になる
+    let mut after_specular = false;
+    for depth in 0..MAX_DEPTH {
+        let hit = world.hit(&ray, EPS, INF);
+        let light = match &hit {
+            Some(hit) => to_spectral(hit.m.emitted(hit), ray.wavelength),
+            None => to_spectral(sky(&ray), ray.wavelength),
+        };
+        if depth < 2 {
+            radiance = radiance + first.mult(throughput.mult(light));
+        } else {
+            indirect = indirect + throughput.mult(light);
         }
-        
-    } else {
-        let t=0.5*(r.d.norm().y+1.0);
-        Vec3::new(1.0,1.0,1.0)*(1.0-t)+Vec3::new(0.5,0.7,1.0)*t
+        let Some(hit) = hit else {
+            if let Some(f) = trace.as_mut() {
+                f(depth, Bounce::Miss);
+            }
+            break;
+        };
+        let Some(scatter) = hit.m.scatter(&ray, &hit) else {
+            if let Some(f) = trace.as_mut() {
+                f(depth, Bounce::Absorb { hit: &hit, emitted: light });
+            }
+            break;
+        };
+        let scatter = filter.regularize(scatter, &hit, after_specular);
+        after_specular = after_specular || (filter.regularize.is_some() && hit.m.is_specular(&hit));
+        let albedo = to_spectral(scatter.albedo, ray.wavelength);
+        if depth == 0 {
+            first = albedo;
+        } else {
+            throughput = throughput.mult(albedo);
+        }
+        if let Some(f) = trace.as_mut() {
+            f(depth, Bounce::Scatter { hit: &hit, dir: scatter.ray.d.norm(), throughput: first.mult(throughput) });
+        }
+        ray = scatter.ray;
     }
+    //2つ目の交点から先の寄与だけを抑え、光源からの直接光は残す
+    radiance + first.mult(filter.clamp_indirect(indirect))
 }
-        
 
-fn main() {
-
-    let args = parameters();
-    println!("{:?}", args);
-    
-    let ASPECT_RATIO=16.0/9.0;
-    let w: usize = 384;
-    let h: usize = ((w as f64)/ASPECT_RATIO) as usize;
-    let samps:usize = 128;
+//1サンプルの色、スペクトルモードではRGBに戻してからクランプする
+fn sample_color(args: &Args, world: &dyn Shape, ray: &Ray, lambda: f64, filter: &FireflyFilter, trace: Trace) -> Color {
+    let mut c = ray_color(ray, world, filter, trace);
+    if args.spectral {
+        c = spectrum_to_rgb(c.x, lambda);
+    }
+    filter.clamp_sample(c)
+}
 
-    let mut image = vec![Color::zero(); (w * h) as usize];
+//1画素のパスを1反射ずつ表示する
+fn trace_pixel(args: &Args, world: &dyn Shape, cam: &dyn CameraModel, filter: &FireflyFilter, (x, y): (usize, usize), w: usize, h: usize) {
+    println!("pixel ({}, {})", x, y);
+    let mut sum = Color::zero();
+    for spp in 0..args.s {
+        let u=(x as f64 + random() ) /(w as f64);
+        let v=(y as f64 + random() ) /(h as f64);
+        let lambda = if args.spectral { sample_wavelength() } else { 0.0 };
+        let Some(ray) = cam.get_ray_at(u, v, lambda) else {
+            println!("sample {}: no ray", spp);
+            continue;
+        };
+        println!("sample {}: uv=({:.4}, {:.4}) wavelength={:.1} o={} d={}", spp, u, v, lambda, ray.o, ray.d.norm());
+        let mut print_bounce = |depth: i64, bounce: Bounce| match bounce {
+            Bounce::Miss => println!("  #{} miss -> sky", depth),
+            Bounce::Scatter { hit, dir, throughput } => println!(
+                "  #{} t={:.4} p={} shape={} material={} front={} dir={} throughput={}",
+                depth, hit.t, hit.p, hit.shape.name(), hit.m.name(), hit.front_face, dir, throughput
+            ),
+            Bounce::Absorb { hit, emitted } => println!(
+                "  #{} t={:.4} p={} shape={} material={} front={} absorbed emitted={}",
+                depth, hit.t, hit.p, hit.shape.name(), hit.m.name(), hit.front_face, emitted
+            ),
+        };
+        let radiance = sample_color(args, world, &ray, lambda, filter, Some(&mut print_bounce));
+        println!("  radiance={}", radiance * cam.exposure());
+        sum = sum + radiance;
+    }
+    println!("mean={}", sum / (args.s.max(1) as f64) * cam.exposure());
+}
 
-// Camera
+//デノイズ用の補助バッファ、一次レイの交点のアルベド・法線・レイに沿った距離
+fn ray_feature(r: &Ray,world:&dyn Shape) -> Feature {
+    if let Some(hit)=world.hit(r,EPS,INF) {
+        let albedo=match hit.m.scatter(r, &hit) {
+            Some(scatter) => scatter.albedo,
+            None => Color::zero(),
+        };
+        Feature::new(albedo, hit.n, hit.t*r.d.length().sqrt())
+    } else {
+        Feature::new(sky(r), Vec3::zero(), INF)
+    }
+}
+        
 
-    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    let lookat = Vec3::new(0.0, 0.0, 0.0);
+//キーフレームの視点からカメラを作る
+//bokehは--bokehから1度だけ作った絞りの形
+fn build_camera(args: &Args, world: &dyn Shape, view: &Keyframe, aperture: f64, bokeh: Option<&Aperture>, w: usize, h: usize) -> Box<dyn CameraModel> {
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = (lookfrom - lookat).length().sqrt();
-    let aperture = 0.1;
-
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
-    );
-    
-	let MAX_DEPTH:i64=32;
 
-    let mut world = ShapeList::new();
-    world.random_scene();
+    //--lensがあればシーンの画角・口径の代わりに実カメラの設定を使う、ピントはシーンのまま
+    let mut cam = match &args.lens {
+        Some(lens) => Camera::physical(view.lookfrom, view.lookat, vup, ASPECT_RATIO, lens, view.focus_dist),
+        None => Camera::new(
+            view.lookfrom,
+            view.lookat,
+            vup,
+            view.vfov,
+            ASPECT_RATIO,
+            aperture,
+            view.focus_dist,
+        ).with_shutter(0.0, 1.0),
+    };
+    if let Some(bokeh) = bokeh {
+        cam = cam.with_aperture(bokeh.clone());
+    }
+    cam = cam.with_cat_eye(args.cat_eye).with_chromatic(args.chromatic.0, args.chromatic.1);
+    //ステレオの目の間隔は指定がなければピント距離の1/30にする
+    let interocular = args.interocular.unwrap_or(view.focus_dist / 30.0);
+    let project = |cam: Camera| -> Box<dyn CameraModel> {
+        match args.camera.as_str() {
+            "ortho" => Box::new(Orthographic::from_camera(cam)),
+            "fisheye" => Box::new(Fisheye::from_camera(cam, 180.0, ASPECT_RATIO)),
+            "equirect" => Box::new(Equirectangular::from_camera(cam)),
+            "stereo" => Box::new(Stereo::from_camera(cam, interocular)),
+            //perspective、名前はparametersで確かめてある
+            _ => Box::new(cam),
+        }
+    };
+    //その画素に実際に写る物を選んだ投影方式で探す
+    if let Some((x, y)) = args.focus {
+        cam = cam.autofocus(world, (x as f64 + 0.5) / (w as f64), (y as f64 + 0.5) / (h as f64), project);
+    }
+    project(cam)
+}
 
+fn render(args: &Args, world: &dyn Shape, cam: &dyn CameraModel, filter: &FireflyFilter, w: usize, h: usize) -> Vec<Color> {
+    let samps:usize = args.s;
 
-    let bands: Vec<(usize, &mut [Color])> = image.chunks_mut(w as usize).enumerate().collect();
-    bands.into_par_iter().for_each(|(y, band)| {
-        for x in 0..w {
+    let mut image = vec![Color::zero(); w * h];
+    let mut features = vec![Feature::zero(); w * h];
+
+    type Band<'a> = (usize, (&'a mut [Color], &'a mut [Feature]));
+    let bands: Vec<Band> = image.chunks_mut(w).zip(features.chunks_mut(w)).enumerate().collect();
+    //--regionの外は黒のまま残す
+    let region = args.region.unwrap_or((0, 0, w, h));
+    let (x0, y0, x1, y1) = region;
+    bands.into_par_iter().filter(|(y, _)| (y0..y1).contains(y)).for_each(|(y, (band, fband))| {
+        for x in x0..x1 {
             let mut r = Vec3::new(0.0,0.0,0.0);
+            let mut f = Feature::zero();
             for _spp in 0..samps {
                 let u=(x as f64 + random() ) /(w as f64);
                 let v=(y as f64 + random() ) /(h as f64);
-                let ray = cam.get_ray(u, v);
-                r = r +ray_color(&ray,&world,MAX_DEPTH)/(samps as f64);
+                //スペクトルモードではサンプルごとに1つの波長を運ぶ
+                let lambda = if args.spectral { sample_wavelength() } else { 0.0 };
+                //魚眼の円の外などレイのない所は黒のまま
+                let Some(ray) = cam.get_ray_at(u, v, lambda) else { continue };
+                let c = sample_color(args, world, &ray, lambda, filter, None);
+                r = r + c/(samps as f64);
+                if args.denoise {
+                    f = f + ray_feature(&ray,world)/(samps as f64);
+                }
             }
-            band[x as usize] = r; 
+            band[x] = r * cam.exposure();
+            fband[x] = f;
         }
         if (y % 20)==0 {
             print!("y={0}  :",y);
@@ -80,6 +206,138 @@ fn main() {
         };
     });
 
-    //    save_ppm_file("image.ppm", image, w, h);
-    save_png_file(&args.output, image, w, h);
+    //領域の外の黒い画素が混ざらないよう、切り出してからデノイズして戻す
+    if args.denoise {
+        let out = Denoiser::new().apply(&crop(&image, w, region), &crop(&features, w, region), x1 - x0, y1 - y0);
+        for (y, row) in out.chunks(x1 - x0).enumerate() {
+            image[(y0 + y) * w + x0..(y0 + y) * w + x1].copy_from_slice(row);
+        }
+    }
+    image
+}
+
+//画像から(x0, y0, x1, y1)の範囲を切り出す
+fn crop<T: Copy>(image: &[T], w: usize, (x0, y0, x1, y1): (usize, usize, usize, usize)) -> Vec<T> {
+    image.chunks(w).skip(y0).take(y1 - y0).flat_map(|row| row[x0..x1].iter().copied()).collect()
+}
+
+fn main() {
+
+    let args = parameters();
+    println!("{:?}", args);
+    
+    let w: usize = args.w;
+    //全天球パノラマは2:1で出力する
+    let h: usize = if args.camera == "equirect" { w / 2 } else { ((w as f64)/ASPECT_RATIO) as usize };
+
+    let mut world = ShapeList::new();
+    //シーンごとの視点 (lookfrom, lookat, vfov, aperture)
+    let (lookfrom, lookat, vfov, aperture) = match args.m {
+        1 => {
+            world.texture_scene();
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), 20.0, 0.1)
+        }
+        2 => {
+            world.earth_scene();
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), 20.0, 0.1)
+        }
+        3 => {
+            world.box_scene();
+            (Vec3::new(278.0, 278.0, -800.0), Vec3::new(278.0, 278.0, 0.0), 40.0, 0.0)
+        }
+        4 => {
+            world.primitive_scene();
+            (Vec3::new(13.0, 3.0, 3.0), Vec3::new(0.0, 0.8, 0.0), 30.0, 0.0)
+        }
+        5 => {
+            world.instance_scene();
+            (Vec3::new(13.0, 4.0, 3.0), Vec3::new(0.0, 0.8, 0.0), 30.0, 0.0)
+        }
+        6 => {
+            world.bouncing_scene();
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), 20.0, 0.1)
+        }
+        7 => {
+            world.smoke_scene();
+            (Vec3::new(278.0, 278.0, -800.0), Vec3::new(278.0, 278.0, 0.0), 40.0, 0.0)
+        }
+        8 => {
+            world.cloud_scene();
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 1.8, 0.0), 35.0, 0.0)
+        }
+        9 => {
+            world.csg_scene();
+            (Vec3::new(13.0, 3.0, 3.0), Vec3::new(0.0, 0.8, 0.8), 30.0, 0.0)
+        }
+        10 => {
+            world.sdf_scene();
+            (Vec3::new(13.0, 3.0, 3.0), Vec3::new(0.0, 1.0, 0.0), 35.0, 0.0)
+        }
+        11 => {
+            world.material_scene();
+            (Vec3::new(0.0, 6.0, 11.0), Vec3::new(0.0, 0.3, 0.7), 35.0, 0.0)
+        }
+        12 => {
+            world.prism_scene();
+            (Vec3::new(0.0, 2.0, 8.0), Vec3::new(0.0, 0.7, 0.0), 35.0, 0.0)
+        }
+        _ => {
+            world.random_scene();
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), 20.0, 0.1)
+        }
+    };
+
+// Camera
+
+    let dist_to_focus = (lookfrom - lookat).length().sqrt();
+    let view = Keyframe::new(0.0, lookfrom, lookat, vfov, dist_to_focus);
+    //マスク画像はフレームごとに読み直さない
+    let bokeh = args.bokeh.as_ref().map(|spec| Aperture::from_spec(spec).unwrap_or_else(|e| panic!("{}", e)));
+
+    //画素の指定は画像の大きさが決まってから確かめる
+    if let Some((x, y)) = args.pixel.filter(|&(x, y)| x >= w || y >= h) {
+        panic!("--pixel {},{} is outside the {}x{} image", x, y, w, h);
+    }
+    if let Some((x0, y0, x1, y1)) = args.region.filter(|&(_, _, x1, y1)| x1 > w || y1 > h) {
+        panic!("--region {},{},{},{} is outside the {}x{} image", x0, y0, x1, y1, w, h);
+    }
+
+    let filter = FireflyFilter::new(args.clamp, args.clamp_indirect, args.regularize);
+    if let Some((x, y)) = args.pixel {
+        let key = match (&args.keys, args.frames) {
+            (Some(file), Some((start, _))) => CameraTrack::load(file, args.interpolation).unwrap_or_else(|e| panic!("{}", e)).at(start as f64),
+            _ => view,
+        };
+        let cam = build_camera(&args, &world, &key, aperture, bokeh.as_ref(), w, h);
+        trace_pixel(&args, &world, cam.as_ref(), &filter, (x, y), w, h);
+        if filter.enabled() {
+            filter.report();
+        }
+        return;
+    }
+
+    match args.frames {
+        None => {
+            let cam = build_camera(&args, &world, &view, aperture, bokeh.as_ref(), w, h);
+            //    save_ppm_file("image.ppm", image, w, h);
+            save_png_file(&args.output, render(&args, &world, cam.as_ref(), &filter, w, h), w, h);
+        }
+        //--keysがなければシーンの視点からlookatのまわりを1周するターンテーブルにする
+        Some((start, end)) => {
+            let track = match &args.keys {
+                Some(file) => CameraTrack::load(file, args.interpolation).unwrap_or_else(|e| panic!("{}", e)),
+                None => CameraTrack::orbit(&view, start as f64, (end + 1) as f64),
+            };
+            for frame in start..=end {
+                let key = track.at(frame as f64);
+                let cam = build_camera(&args, &world, &key, aperture, bokeh.as_ref(), w, h);
+                let output = frame_name(&args.output, frame);
+                println!("frame {} -> {}", frame, output);
+                save_png_file(&output, render(&args, &world, cam.as_ref(), &filter, w, h), w, h);
+            }
+        }
+    }
+    if filter.enabled() {
+        filter.report();
+    }
 }
@@ -0,0 +1,56 @@
+use crate::raymod::*;
+
+//軸平行境界ボックス
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(a: Vec3, b: Vec3) -> Self {
+        Self {
+            min: Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+    pub fn surrounding(&self, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(self.min.x.min(b.min.x), self.min.y.min(b.min.y), self.min.z.min(b.min.z)),
+            max: Vec3::new(self.max.x.max(b.max.x), self.max.y.max(b.max.y), self.max.z.max(b.max.z)),
+        }
+    }
+    //厚みのない軸を最低deltaだけ広げる
+    pub fn pad(&self, delta: f64) -> Aabb {
+        let pad = |lo: f64, hi: f64| if hi - lo < delta { (lo - delta / 2.0, hi + delta / 2.0) } else { (lo, hi) };
+        let (x0, x1) = pad(self.min.x, self.max.x);
+        let (y0, y1) = pad(self.min.y, self.max.y);
+        let (z0, z1) = pad(self.min.z, self.max.z);
+        Aabb { min: Vec3::new(x0, y0, z0), max: Vec3::new(x1, y1, z1) }
+    }
+    //スラブ法
+    pub fn hit(&self, r: &Ray, t0: f64, t1: f64) -> bool {
+        self.intersect(r, t0, t1).is_some()
+    }
+    //ボックス内にある区間(tmin,tmax)
+    pub fn intersect(&self, r: &Ray, mut t0: f64, mut t1: f64) -> Option<(f64, f64)> {
+        for (o, d, lo, hi) in [
+            (r.o.x, r.d.x, self.min.x, self.max.x),
+            (r.o.y, r.d.y, self.min.y, self.max.y),
+            (r.o.z, r.d.z, self.min.z, self.max.z),
+        ] {
+            let inv_d = 1.0 / d;
+            let mut ta = (lo - o) * inv_d;
+            let mut tb = (hi - o) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut ta, &mut tb);
+            }
+            t0 = if ta > t0 { ta } else { t0 };
+            t1 = if tb < t1 { tb } else { t1 };
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
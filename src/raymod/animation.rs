@@ -0,0 +1,186 @@
+use crate::raymod::*;
+
+use std::f64::consts::PI;
+
+//オービットで1周に置くキーの数
+const ORBIT_KEYS: usize = 36;
+
+//ある時刻(フレーム番号)でのカメラの設定
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe {
+    pub time: f64,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, lookfrom: Vec3, lookat: Vec3, vfov: f64, focus_dist: f64) -> Self {
+        Self { time, lookfrom, lookat, vfov, focus_dist }
+    }
+    //キーの重み付き和、線形補間もスプラインもこの形になる
+    fn weighted(keys: [(&Keyframe, f64); 4]) -> Keyframe {
+        let mut k = Keyframe::new(0.0, Vec3::zero(), Vec3::zero(), 0.0, 0.0);
+        for (key, w) in keys {
+            k.time += key.time * w;
+            k.lookfrom = k.lookfrom + key.lookfrom * w;
+            k.lookat = k.lookat + key.lookat * w;
+            k.vfov += key.vfov * w;
+            k.focus_dist += key.focus_dist * w;
+        }
+        k
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Interpolation {
+    Linear,
+    //Catmull-Romスプライン、キーを必ず通る
+    CatmullRom,
+}
+
+//時刻順に並んだキーフレームの列
+pub struct CameraTrack {
+    pub keys: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+}
+
+#[allow(dead_code)]
+impl CameraTrack {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self { keys: Vec::new(), interpolation }
+    }
+    pub fn with_key(mut self, key: Keyframe) -> Self {
+        let i = self.keys.partition_point(|k| k.time <= key.time);
+        self.keys.insert(i, key);
+        self
+    }
+    //lookatのまわりをy軸回転で1周する、t1でt0の位置に戻るので連番がループする
+    pub fn orbit(base: &Keyframe, t0: f64, t1: f64) -> Self {
+        let arm = base.lookfrom - base.lookat;
+        (0..=ORBIT_KEYS).fold(Self::new(Interpolation::CatmullRom), |track, i| {
+            let a = i as f64 / ORBIT_KEYS as f64;
+            let (sin, cos) = (2.0 * PI * a).sin_cos();
+            let lookfrom = base.lookat + Vec3::new(arm.x * cos + arm.z * sin, arm.y, -arm.x * sin + arm.z * cos);
+            track.with_key(Keyframe { time: t0 + (t1 - t0) * a, lookfrom, ..*base })
+        })
+    }
+    //1行に1キー: 時刻 lookfrom(x y z) lookat(x y z) vfov [focus]、#から行末はコメント
+    //focusを省くとlookatまでの距離を使う
+    pub fn load(filename: &str, interpolation: Interpolation) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(filename)?;
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+        let mut track = Self::new(interpolation);
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let v: Vec<f64> = line
+                .split_whitespace()
+                .map(|x| x.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid(format!("{}:{}: {}", filename, n + 1, e)))?;
+            if v.len() < 8 {
+                return Err(invalid(format!("{}:{}: expected 8 or 9 numbers", filename, n + 1)));
+            }
+            let lookfrom = Vec3::new(v[1], v[2], v[3]);
+            let lookat = Vec3::new(v[4], v[5], v[6]);
+            let focus_dist = v.get(8).copied().unwrap_or_else(|| (lookfrom - lookat).length().sqrt());
+            track = track.with_key(Keyframe::new(v[0], lookfrom, lookat, v[7], focus_dist));
+        }
+        if track.keys.is_empty() {
+            return Err(invalid(format!("{}: no keyframes", filename)));
+        }
+        Ok(track)
+    }
+    //範囲外では端のキーのまま止まる
+    pub fn at(&self, time: f64) -> Keyframe {
+        let n = self.keys.len();
+        let i = self.keys.partition_point(|k| k.time <= time);
+        if i == 0 {
+            return self.keys[0];
+        }
+        if i == n {
+            return self.keys[n - 1];
+        }
+        let (k1, k2) = (&self.keys[i - 1], &self.keys[i]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+        let k = match self.interpolation {
+            Interpolation::Linear => Keyframe::weighted([(k1, 1.0 - t), (k2, t), (k1, 0.0), (k2, 0.0)]),
+            Interpolation::CatmullRom => {
+                //両端は端のキーを複製して補う
+                let k0 = &self.keys[i.saturating_sub(2)];
+                let k3 = &self.keys[(i + 1).min(n - 1)];
+                let (t2, t3) = (t * t, t * t * t);
+                Keyframe::weighted([
+                    (k0, (-t3 + 2.0 * t2 - t) / 2.0),
+                    (k1, (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0),
+                    (k2, (-3.0 * t3 + 4.0 * t2 + t) / 2.0),
+                    (k3, (t3 - t2) / 2.0),
+                ])
+            }
+        };
+        Keyframe { time, ..k }
+    }
+}
+
+//連番の出力ファイル名、image.png の 12 フレーム目は image_0012.png
+pub fn frame_name(output: &str, frame: usize) -> String {
+    let path = std::path::Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    let name = format!("{}_{:04}.{}", stem, frame, ext);
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.join(name).to_string_lossy().into_owned(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, x: f64) -> Keyframe {
+        Keyframe::new(time, Vec3::new(x, 0.0, 0.0), Vec3::zero(), 40.0 + x, 1.0)
+    }
+
+    fn track(interpolation: Interpolation) -> CameraTrack {
+        CameraTrack::new(interpolation).with_key(key(10.0, 4.0)).with_key(key(0.0, 0.0)).with_key(key(5.0, 2.0)).with_key(key(20.0, 0.0))
+    }
+
+    #[test]
+    fn linear_interpolates_between_keys() {
+        let k = track(Interpolation::Linear).at(7.5);
+        assert!((k.lookfrom.x - 3.0).abs() < 1e-12);
+        assert!((k.vfov - 43.0).abs() < 1e-12);
+        assert_eq!(k.time, 7.5);
+    }
+
+    #[test]
+    fn track_holds_end_keys_outside_range() {
+        let t = track(Interpolation::CatmullRom);
+        assert_eq!(t.at(-3.0).lookfrom.x, 0.0);
+        assert_eq!(t.at(25.0).lookfrom.x, 0.0);
+        assert_eq!(t.at(25.0).vfov, 40.0);
+    }
+
+    #[test]
+    fn spline_passes_through_keys() {
+        let t = track(Interpolation::CatmullRom);
+        for (time, x) in [(0.0, 0.0), (5.0, 2.0), (10.0, 4.0), (20.0, 0.0)] {
+            assert!((t.at(time).lookfrom.x - x).abs() < 1e-12, "{}", time);
+        }
+        //キーの前後で途切れない
+        let (a, b) = (t.at(10.0 - 1e-9).lookfrom.x, t.at(10.0 + 1e-9).lookfrom.x);
+        assert!((a - b).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frame_names_are_numbered() {
+        assert_eq!(frame_name("image.png", 12), "image_0012.png");
+        assert_eq!(frame_name("out/shot.jpg", 3), std::path::Path::new("out").join("shot_0003.jpg").to_string_lossy());
+        assert_eq!(frame_name("movie", 1), "movie_0001.png");
+    }
+}
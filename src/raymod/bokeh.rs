@@ -0,0 +1,159 @@
+use crate::raymod::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+//マスク画像の棄却サンプリングの試行回数
+const MASK_TRIES: usize = 64;
+
+//絞りの形、ボケの形になる
+#[derive(Clone)]
+pub enum Aperture {
+    Disk,
+    //正多角形の絞り羽根、rotationは度
+    Polygon { blades: usize, rotation: f64 },
+    //画像の明るさを透過率とする絞り、画像全体がレンズの外接正方形に対応する
+    Mask(Arc<ImageTexture>),
+}
+
+impl Aperture {
+    //"6" や "6,15" なら羽根の枚数と回転、それ以外は画像ファイル名とみなす
+    pub fn from_spec(spec: &str) -> Result<Aperture, String> {
+        let v: Vec<&str> = spec.split(',').map(|x| x.trim()).collect();
+        if let Ok(blades) = v[0].parse::<usize>() {
+            if blades < 3 {
+                return Err(format!("{}: at least 3 blades are needed", spec));
+            }
+            let rotation = match v[1..] {
+                [] => 0.0,
+                [r] => r.parse().map_err(|e| format!("{}: {}", spec, e))?,
+                _ => return Err(format!("{}: expected BLADES[,ROTATION]", spec)),
+            };
+            return Ok(Aperture::Polygon { blades, rotation });
+        }
+        ImageTexture::load_data(spec, WrapMode::Clamp)
+            .map(|tex| Aperture::Mask(Arc::new(tex)))
+            .map_err(|e| format!("{}: {}", spec, e))
+    }
+    //単位円板に収まる絞り内の一様な点
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Disk => Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                //中心と隣り合う2頂点の三角形を1つ選び、その中で一様に取る
+                let n = *blades as f64;
+                let i = ((random() * n) as usize).min(blades - 1) as f64;
+                let a0 = rotation.to_radians() + 2.0 * PI * i / n;
+                let a1 = a0 + 2.0 * PI / n;
+                let (mut r1, mut r2) = (random(), random());
+                if r1 + r2 > 1.0 {
+                    r1 = 1.0 - r1;
+                    r2 = 1.0 - r2;
+                }
+                Vec3::new(a0.cos(), a0.sin(), 0.0) * r1 + Vec3::new(a1.cos(), a1.sin(), 0.0) * r2
+            }
+            Aperture::Mask(tex) => {
+                for _ in 0..MASK_TRIES {
+                    let p = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
+                    let c = tex.sample((p.x + 1.0) / 2.0, (p.y + 1.0) / 2.0);
+                    if random() < (c.x + c.y + c.z) / 3.0 {
+                        return p;
+                    }
+                }
+                Vec3::zero()
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Camera {
+    pub fn with_aperture(mut self, aperture: Aperture) -> Camera {
+        self.aperture = aperture;
+        self
+    }
+    //画面端で絞りがレンズ枠に欠けられる強さ、0で無効、1で画面の縦端でずれがレンズ半径と同じになる
+    pub fn with_cat_eye(mut self, cat_eye: f64) -> Camera {
+        self.cat_eye = cat_eye;
+        self
+    }
+    //色収差、スペクトルモードでだけ効く
+    //lateralは倍率の、axialはピント距離の、可視域の端での相対的なずれ
+    pub fn with_chromatic(mut self, lateral: f64, axial: f64) -> Camera {
+        self.lateral_ca = lateral;
+        self.axial_ca = axial;
+        self
+    }
+    //レンズ上の点、単位円板の座標で返す
+    //口径食で切り取られた所に落ちたらNone、そのサンプルは光を運ばないので画面端が暗くなる
+    pub(crate) fn sample_lens(&self, s: f64, t: f64) -> Option<Vec3> {
+        let p = self.aperture.sample();
+        if self.cat_eye <= 0.0 || self.lens_radius <= 0.0 {
+            return Some(p);
+        }
+        //画面中心からのずれの分だけ、もう1つの円板で絞りを切り取る
+        let aspect = (self.horizontal.length() / self.vertical.length()).sqrt();
+        let mut c = Vec3::new((2.0 * s - 1.0) * aspect, 1.0 - 2.0 * t, 0.0) * self.cat_eye;
+        if c.length() > 1.9 * 1.9 {
+            c = c.norm() * 1.9;
+        }
+        ((p - c).length() < 1.0).then_some(p)
+    }
+    //波長による像の倍率とピント面のずれ、550nmで1になる
+    pub(crate) fn chromatic_target(&self, p: Vec3, wavelength: f64) -> Vec3 {
+        if wavelength <= 0.0 {
+            return p;
+        }
+        let k = (wavelength - 550.0) / ((LAMBDA_MAX - LAMBDA_MIN) / 2.0);
+        let center = self.origin - self.w * self.focus_dist;
+        let p = center + (p - center) * (1.0 + self.lateral_ca * k);
+        self.origin + (p - self.origin) * (1.0 + self.axial_ca * k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_in_unit_disk() {
+        for aperture in [Aperture::Disk, Aperture::Polygon { blades: 3, rotation: 0.0 }, Aperture::Polygon { blades: 6, rotation: 15.0 }] {
+            for _ in 0..10000 {
+                let p = aperture.sample();
+                assert!(p.length() <= 1.0 + 1e-12 && p.z == 0.0, "{}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn polygon_samples_stay_inside_edges() {
+        //正六角形の内接円の半径はcos(30度)、辺の外に出る点はない
+        let aperture = Aperture::Polygon { blades: 6, rotation: 0.0 };
+        let apothem = (PI / 6.0).cos();
+        for _ in 0..10000 {
+            let p = aperture.sample();
+            for i in 0..6 {
+                let a = PI / 6.0 + PI / 3.0 * i as f64;
+                assert!(p.x * a.cos() + p.y * a.sin() <= apothem + 1e-12, "{}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn cat_eye_darkens_the_corners() {
+        let cam = Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 16.0 / 9.0, 1.0, 1.0).with_cat_eye(0.5);
+        let pass = |s: f64, t: f64| (0..20000).filter(|_| cam.sample_lens(s, t).is_some()).count();
+        let (center, corner) = (pass(0.5, 0.5), pass(0.0, 0.0));
+        assert_eq!(center, 20000);
+        assert!(corner < center * 3 / 4, "{} {}", corner, center);
+        assert!(corner > 0);
+    }
+
+    #[test]
+    fn bad_specs_are_errors() {
+        assert!(matches!(Aperture::from_spec("6,15"), Ok(Aperture::Polygon { blades: 6, .. })));
+        for spec in ["0", "2", "6,x", "6,1,2", "no_such_mask.png"] {
+            assert!(Aperture::from_spec(spec).is_err(), "{}", spec);
+        }
+    }
+}
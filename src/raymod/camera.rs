@@ -0,0 +1,152 @@
+use crate::raymod::*;
+
+use std::f64::consts::PI;
+
+//画面上の点(s,t)からレイを作る投影方式、(0,0)が左上
+//画面に写らない点ではNoneを返す
+pub trait CameraModel: Sync + Send {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray>;
+    //波長付きのレイ、波長で曲がり方の変わる投影だけが上書きする
+    fn get_ray_at(&self, s: f64, t: f64, wavelength: f64) -> Option<Ray> {
+        self.get_ray(s, t).map(|r| r.with_wavelength(wavelength))
+    }
+    //画像に掛ける露出倍率
+    fn exposure(&self) -> f64 {
+        1.0
+    }
+}
+
+impl CameraModel for Camera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        Camera::get_ray(self, s, t)
+    }
+    fn get_ray_at(&self, s: f64, t: f64, wavelength: f64) -> Option<Ray> {
+        Camera::get_ray_at(self, s, t, wavelength)
+    }
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+impl Camera {
+    pub fn sample_time(&self) -> f64 {
+        self.time0 + random() * (self.time1 - self.time0)
+    }
+}
+
+//平行投影、透視カメラのピント面と同じ範囲を写す
+pub struct Orthographic {
+    pub cam: Camera,
+}
+
+impl Orthographic {
+    pub fn from_camera(cam: Camera) -> Self {
+        Self { cam }
+    }
+}
+
+impl CameraModel for Orthographic {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let c = &self.cam;
+        let o = c.upper_left_corner + c.horizontal * s - c.vertical * t + c.w * c.focus_dist;
+        Some(Ray::with_time(o, c.w * -1.0, c.sample_time()))
+    }
+    fn exposure(&self) -> f64 {
+        self.cam.exposure
+    }
+}
+
+//等距離射影の魚眼、画面の短辺に内接する円にfov度を写す
+pub struct Fisheye {
+    pub cam: Camera,
+    pub fov: f64,
+    pub aspect_ratio: f64,
+}
+
+impl Fisheye {
+    pub fn from_camera(cam: Camera, fov: f64, aspect_ratio: f64) -> Self {
+        Self { cam, fov, aspect_ratio }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 1.0 - 2.0 * t;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let theta = r * self.fov.to_radians() / 2.0;
+        let phi = y.atan2(x);
+        let c = &self.cam;
+        let d = c.u * (theta.sin() * phi.cos()) + c.v * (theta.sin() * phi.sin()) - c.w * theta.cos();
+        Some(Ray::with_time(c.origin, d, c.sample_time()))
+    }
+    fn exposure(&self) -> f64 {
+        self.cam.exposure
+    }
+}
+
+//全天球パノラマ、横が経度360度、縦が緯度180度で画面中央が視線方向
+pub struct Equirectangular {
+    pub cam: Camera,
+}
+
+impl Equirectangular {
+    pub fn from_camera(cam: Camera) -> Self {
+        Self { cam }
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (0.5 - t) * PI;
+        let c = &self.cam;
+        let d = c.u * (theta.cos() * phi.sin()) + c.v * theta.sin() - c.w * (theta.cos() * phi.cos());
+        Some(Ray::with_time(c.origin, d, c.sample_time()))
+    }
+    fn exposure(&self) -> f64 {
+        self.cam.exposure
+    }
+}
+
+//左右に並べた平行法のステレオ、左半分が左目
+pub struct Stereo {
+    pub left: Camera,
+    pub right: Camera,
+}
+
+impl Stereo {
+    //各目は画面の半分の幅を受け持つので横の範囲も半分にする
+    pub fn from_camera(cam: Camera, interocular: f64) -> Self {
+        let eye = |sign: f64| {
+            let offset = cam.u * (sign * interocular / 2.0);
+            let horizontal = cam.horizontal / 2.0;
+            Camera {
+                origin: cam.origin + offset,
+                upper_left_corner: cam.upper_left_corner + offset + horizontal / 2.0,
+                horizontal,
+                ..cam.clone()
+            }
+        };
+        Self { left: eye(-1.0), right: eye(1.0) }
+    }
+}
+
+impl CameraModel for Stereo {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_ray_at(s, t, 0.0)
+    }
+    fn get_ray_at(&self, s: f64, t: f64, wavelength: f64) -> Option<Ray> {
+        if s < 0.5 {
+            self.left.get_ray_at(s * 2.0, t, wavelength)
+        } else {
+            self.right.get_ray_at(s * 2.0 - 1.0, t, wavelength)
+        }
+    }
+    fn exposure(&self) -> f64 {
+        self.left.exposure
+    }
+}
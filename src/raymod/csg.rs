@@ -0,0 +1,195 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//1つの形状から集める境界の最大数
+pub const MAX_EVENTS: usize = 32;
+
+//CSGの演算、閉じた形状同士でのみ意味を持つ
+#[derive(Copy, Clone, Debug)]
+enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+//t0から先の境界と、レイの始点が内側にあるか
+//最初の境界が出る向きなら始点は内側にある
+fn events<'a>(shape: &'a dyn Shape, r: &Ray, t0: f64) -> (Vec<HitInfo<'a>>, bool) {
+    let out = shape.boundaries(r, t0);
+    let inside = match out.first() {
+        Some(hit) => !hit.front_face,
+        //前方に境界がなければ後ろ向きに調べる、平面のような無限の形状のため
+        None => {
+            let back = Ray::with_time(r.at(t0), r.d * -1.0, r.time).with_wavelength(r.wavelength);
+            shape.hit(&back, 0.0, INF).is_some_and(|h| !h.front_face)
+        }
+    };
+    (out, inside)
+}
+
+//2つの境界列を1回ずつ集めて並べて歩き、演算結果の内外が切り替わる点をすべて返す
+//子がCSGでも境界列をまとめて受け取るので、入れ子にしても手間は形状の数に比例する
+fn csg_boundaries<'a>(a: &'a dyn Shape, b: &'a dyn Shape, op: CsgOp, r: &Ray, t0: f64) -> Vec<HitInfo<'a>> {
+    let (ea, mut in_a) = events(a, r, t0);
+    if ea.is_empty() && !in_a && !matches!(op, CsgOp::Union) {
+        return Vec::new();
+    }
+    let (eb, mut in_b) = events(b, r, t0);
+    let mut inside = op.inside(in_a, in_b);
+    let mut out = Vec::new();
+    let mut ia = ea.into_iter().peekable();
+    let mut ib = eb.into_iter().peekable();
+    loop {
+        let from_a = match (ia.peek(), ib.peek()) {
+            (Some(x), Some(y)) => x.t <= y.t,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return out,
+        };
+        let Some(mut hit) = (if from_a { ia.next() } else { ib.next() }) else { return out };
+        if from_a {
+            in_a = hit.front_face;
+        } else {
+            in_b = hit.front_face;
+        }
+        let now = op.inside(in_a, in_b);
+        if now != inside {
+            //結果の内側へ入る境界を表面とする、差のBの面はここで裏返る
+            hit.front_face = now;
+            out.push(hit);
+        }
+        inside = now;
+    }
+}
+
+fn csg_hit<'a>(a: &'a dyn Shape, b: &'a dyn Shape, op: CsgOp, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'a>> {
+    csg_boundaries(a, b, op, r, t0).into_iter().next().filter(|hit| hit.t < t1)
+}
+
+//和集合
+pub struct Union {
+    pub a: Arc<dyn Shape>,
+    pub b: Arc<dyn Shape>,
+}
+
+impl Union {
+    pub fn new(a: Arc<dyn Shape>, b: Arc<dyn Shape>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Shape for Union {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        csg_hit(self.a.as_ref(), self.b.as_ref(), CsgOp::Union, r, t0, t1)
+    }
+    fn boundaries(&self, r: &Ray, t0: f64) -> Vec<HitInfo<'_>> {
+        csg_boundaries(self.a.as_ref(), self.b.as_ref(), CsgOp::Union, r, t0)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.a.bounding_box()?.surrounding(&self.b.bounding_box()?))
+    }
+}
+
+//共通部分、2つの球でレンズが作れる
+pub struct Intersection {
+    pub a: Arc<dyn Shape>,
+    pub b: Arc<dyn Shape>,
+}
+
+impl Intersection {
+    pub fn new(a: Arc<dyn Shape>, b: Arc<dyn Shape>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Shape for Intersection {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        csg_hit(self.a.as_ref(), self.b.as_ref(), CsgOp::Intersection, r, t0, t1)
+    }
+    fn boundaries(&self, r: &Ray, t0: f64) -> Vec<HitInfo<'_>> {
+        csg_boundaries(self.a.as_ref(), self.b.as_ref(), CsgOp::Intersection, r, t0)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        match (self.a.bounding_box(), self.b.bounding_box()) {
+            (Some(a), Some(b)) => {
+                let min = Vec3::new(a.min.x.max(b.min.x), a.min.y.max(b.min.y), a.min.z.max(b.min.z));
+                let max = Vec3::new(a.max.x.min(b.max.x), a.max.y.min(b.max.y), a.max.z.min(b.max.z));
+                //重ならなければ中身は空、厚さ0の箱にして裏返った箱を作らない
+                let max = Vec3::new(max.x.max(min.x), max.y.max(min.y), max.z.max(min.z));
+                Some(Aabb { min, max })
+            }
+            (Some(a), None) => Some(a),
+            (None, b) => b,
+        }
+    }
+}
+
+//差集合 A - B、薄い殻や穴あき形状に使う
+pub struct Difference {
+    pub a: Arc<dyn Shape>,
+    pub b: Arc<dyn Shape>,
+}
+
+impl Difference {
+    pub fn new(a: Arc<dyn Shape>, b: Arc<dyn Shape>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Shape for Difference {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        csg_hit(self.a.as_ref(), self.b.as_ref(), CsgOp::Difference, r, t0, t1)
+    }
+    fn boundaries(&self, r: &Ray, t0: f64) -> Vec<HitInfo<'_>> {
+        csg_boundaries(self.a.as_ref(), self.b.as_ref(), CsgOp::Difference, r, t0)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(x: f64, radius: f64) -> Arc<dyn Shape> {
+        Arc::new(Sphere::new(Vec3::new(x, 0.0, 0.0), radius, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))))
+    }
+
+    #[test]
+    fn nested_difference_hits_carved_surface() {
+        //半径3の球から-x側を入れ子の差で削ると、軸上ではx=0の削った面で入りx=3で出る
+        let mut shape = sphere(0.0, 3.0);
+        for radius in [2.0, 1.0, 0.5] {
+            shape = Arc::new(Difference::new(shape, sphere(-radius, radius)));
+        }
+        let r = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = shape.hit(&r, 0.0, INF).unwrap();
+        assert!((hit.t - 10.0).abs() < 1e-6, "t = {}", hit.t);
+        assert!(hit.front_face);
+        let all = shape.boundaries(&r, 0.0);
+        let ts: Vec<f64> = all.iter().map(|h| h.t).collect();
+        assert_eq!(ts.len(), 2, "{:?}", ts);
+        assert!((ts[1] - 13.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn disjoint_intersection_has_empty_box() {
+        let shape = Intersection::new(sphere(-5.0, 1.0), sphere(5.0, 1.0));
+        let b = shape.bounding_box().unwrap();
+        assert!(b.min.x <= b.max.x && b.min.y <= b.max.y && b.min.z <= b.max.z);
+        let r = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(shape.hit(&r, 0.0, INF).is_none());
+    }
+}
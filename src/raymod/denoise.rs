@@ -0,0 +1,179 @@
+use crate::raymod::*;
+
+use rayon::prelude::*;
+use std::ops::{Add, Div};
+
+//最初の交点で取得する補助バッファ(AOV)
+#[derive(Copy, Clone, Debug)]
+pub struct Feature {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+}
+
+impl Feature {
+    pub fn new(albedo: Color, normal: Vec3, depth: f64) -> Self {
+        Self { albedo, normal, depth }
+    }
+    pub fn zero() -> Self {
+        Self::new(Color::zero(), Vec3::zero(), 0.0)
+    }
+}
+
+impl Add for Feature {
+    type Output = Feature;
+    fn add(self, rhs: Self) -> Self {
+        Feature::new(self.albedo + rhs.albedo, self.normal + rhs.normal, self.depth + rhs.depth)
+    }
+}
+
+impl Div<f64> for Feature {
+    type Output = Feature;
+    fn div(self, rhs: f64) -> Self {
+        Feature::new(self.albedo / rhs, self.normal / rhs, self.depth / rhs)
+    }
+}
+
+// Edge-avoiding A-Trous wavelet filter (Dammertz et al. 2010)
+// 放射輝度はアルベドで割って照明成分だけをぼかし、最後にアルベドを掛け戻す
+pub struct Denoiser {
+    pub iterations: usize,
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    pub sigma_depth: f64,
+    pub sigma_albedo: f64,
+}
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.1,
+        }
+    }
+
+    pub fn apply(&self, image: &[Color], features: &[Feature], w: usize, h: usize) -> Vec<Color> {
+        let albedo: Vec<Color> = features.iter().map(|f| demod_albedo(f.albedo)).collect();
+        let mut irradiance: Vec<Color> = image
+            .iter()
+            .zip(albedo.iter())
+            .map(|(c, a)| Vec3::new(c.x / a.x, c.y / a.y, c.z / a.z))
+            .collect();
+
+        for i in 0..self.iterations {
+            let step = 1usize << i;
+            //反復ごとに色の許容幅を半分にする
+            let sigma_color = self.sigma_color / (1 << i) as f64;
+            let src = &irradiance;
+            let mut dst = vec![Color::zero(); w * h];
+            dst.par_chunks_mut(w).enumerate().for_each(|(y, band)| {
+                for (x, out) in band.iter_mut().enumerate() {
+                    *out = self.filter_pixel(src, features, w, h, x, y, step, sigma_color);
+                }
+            });
+            irradiance = dst;
+        }
+
+        irradiance.iter().zip(albedo.iter()).map(|(c, a)| c.mult(*a)).collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn filter_pixel(&self, src: &[Color], features: &[Feature], w: usize, h: usize,
+                    x: usize, y: usize, step: usize, sigma_color: f64) -> Color {
+        let p = x + y * w;
+        let (cp, fp) = (src[p], features[p]);
+        let mut sum = Color::zero();
+        let mut wsum = 0.0;
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let qy = y as i64 + (j as i64 - 2) * step as i64;
+            if qy < 0 || qy >= h as i64 {
+                continue;
+            }
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let qx = x as i64 + (i as i64 - 2) * step as i64;
+                if qx < 0 || qx >= w as i64 {
+                    continue;
+                }
+                let q = qx as usize + qy as usize * w;
+                let (cq, fq) = (src[q], features[q]);
+
+                let wc = (-(cp - cq).length() / (sigma_color * sigma_color)).exp();
+                let wn = (-(fp.normal - fq.normal).length() / (self.sigma_normal * self.sigma_normal)).exp();
+                let wa = (-(fp.albedo - fq.albedo).length() / (self.sigma_albedo * self.sigma_albedo)).exp();
+                let dz = (fp.depth - fq.depth).abs() / (self.sigma_depth * fp.depth.max(EPS) * step as f64);
+                let wz = (-dz).exp();
+
+                let weight = kx * ky * wc * wn * wa * wz;
+                sum = sum + cq * weight;
+                wsum += weight;
+            }
+        }
+        sum / wsum
+    }
+}
+
+//黒いアルベドで割らないよう下限を設ける
+fn demod_albedo(a: Color) -> Color {
+    Vec3::new(a.x.max(0.01), a.y.max(0.01), a.z.max(0.01))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 16;
+    const H: usize = 8;
+
+    //左半分と右半分で値の違うバッファ
+    fn split<T: Copy>(left: T, right: T) -> Vec<T> {
+        (0..W * H).map(|i| if i % W < W / 2 { left } else { right }).collect()
+    }
+
+    fn close(a: Color, b: Color, tol: f64) -> bool {
+        (a.x - b.x).abs() < tol && (a.y - b.y).abs() < tol && (a.z - b.z).abs() < tol
+    }
+
+    #[test]
+    fn flat_image_is_unchanged() {
+        let c = Vec3::new(0.3, 0.5, 0.7);
+        let f = Feature::new(Vec3::new(0.6, 0.6, 0.6), Vec3::new(0.0, 1.0, 0.0), 4.0);
+        let out = Denoiser::new().apply(&vec![c; W * H], &vec![f; W * H], W, H);
+        assert!(out.iter().all(|&o| close(o, c, 1e-12)));
+    }
+
+    #[test]
+    fn normal_edge_is_kept() {
+        //明るさの差は小さく、色の重みだけでは境界で止まらない
+        let (bright, dark) = (Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.9, 0.9, 0.9));
+        let image = split(bright, dark);
+        let albedo = Vec3::new(0.5, 0.5, 0.5);
+        let features = split(
+            Feature::new(albedo, Vec3::new(0.0, 0.0, 1.0), 4.0),
+            Feature::new(albedo, Vec3::new(1.0, 0.0, 0.0), 4.0),
+        );
+        let out = Denoiser::new().apply(&image, &features, W, H);
+        for y in 0..H {
+            assert!(close(out[y * W + W / 2 - 1], bright, 1e-3), "{}", out[y * W + W / 2 - 1]);
+            assert!(close(out[y * W + W / 2], dark, 1e-3), "{}", out[y * W + W / 2]);
+        }
+    }
+
+    #[test]
+    fn albedo_edge_is_kept() {
+        //照明も左右で違う、アルベドの境界で止まらなければ混ざる
+        let (a0, a1) = (Vec3::new(0.8, 0.8, 0.8), Vec3::new(0.2, 0.2, 0.2));
+        let image = split(a0, a1 * 0.5);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let features = split(Feature::new(a0, n, 4.0), Feature::new(a1, n, 4.0));
+        let out = Denoiser::new().apply(&image, &features, W, H);
+        for y in 0..H {
+            assert!(close(out[y * W + W / 2 - 1], a0, 1e-3));
+            assert!(close(out[y * W + W / 2], a1 * 0.5, 1e-3));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::raymod::*;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//削った放射輝度を整数で数えるときの単位
+const ENERGY_SCALE: f64 = 1e6;
+
+//ホタル(孤立した明るい画素)を抑える設定と、その効き具合の集計
+//どれもバイアスが入る代わりに分散を減らす
+#[derive(Default)]
+pub struct FireflyFilter {
+    //1サンプルの放射輝度の最大成分の上限
+    pub clamp: Option<f64>,
+    //2回目以降の反射で届いた分だけの上限
+    pub clamp_indirect: Option<f64>,
+    //最初の鏡面反射・屈折より後の鏡面に加える粗さ
+    pub regularize: Option<f64>,
+    samples: AtomicU64,
+    clamped: AtomicU64,
+    clamped_indirect: AtomicU64,
+    removed: AtomicU64,
+    specular: AtomicU64,
+    regularized: AtomicU64,
+}
+
+impl FireflyFilter {
+    pub fn new(clamp: Option<f64>, clamp_indirect: Option<f64>, regularize: Option<f64>) -> Self {
+        Self { clamp, clamp_indirect, regularize, ..Default::default() }
+    }
+    pub fn enabled(&self) -> bool {
+        self.clamp.is_some() || self.clamp_indirect.is_some() || self.regularize.is_some()
+    }
+    //最大成分がlimitを超えたら色相を保ったまま縮める
+    fn limit(&self, c: Color, limit: Option<f64>, counter: &AtomicU64) -> Color {
+        let Some(limit) = limit else { return c };
+        let m = c.x.max(c.y).max(c.z);
+        if m <= limit {
+            return c;
+        }
+        counter.fetch_add(1, Ordering::Relaxed);
+        let out = c * (limit / m);
+        //スペクトルモードでは負の成分が出るので、正の部分だけで削った量を数える
+        let positive = |c: Color| c.x.max(0.0) + c.y.max(0.0) + c.z.max(0.0);
+        let removed = (positive(c) - positive(out)) / 3.0;
+        self.removed.fetch_add((removed * ENERGY_SCALE) as u64, Ordering::Relaxed);
+        out
+    }
+    pub fn clamp_sample(&self, c: Color) -> Color {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        self.limit(c, self.clamp, &self.clamped)
+    }
+    pub fn clamp_indirect(&self, c: Color) -> Color {
+        self.limit(c, self.clamp_indirect, &self.clamped_indirect)
+    }
+    //鏡面の散乱方向をMetalのfuzzと同じ要領でぼかす、面の反対側に回り込むならそのまま
+    //after_specularはパスがすでに鏡面を通ったか、最初の鏡面はぼかさない
+    pub fn regularize(&self, mut scatter: ScatterInfo, hit: &HitInfo, after_specular: bool) -> ScatterInfo {
+        let Some(roughness) = self.regularize else { return scatter };
+        if !hit.m.is_specular(hit) {
+            return scatter;
+        }
+        self.specular.fetch_add(1, Ordering::Relaxed);
+        if !after_specular {
+            return scatter;
+        }
+        let d = scatter.ray.d.norm();
+        let rough = d + Vec3::random_hemisphere() * roughness;
+        if rough.dot(&hit.ng) * d.dot(&hit.ng) > 0.0 {
+            scatter.ray.d = rough;
+            self.regularized.fetch_add(1, Ordering::Relaxed);
+        }
+        scatter
+    }
+    pub fn report(&self) {
+        let samples = self.samples.load(Ordering::Relaxed).max(1) as f64;
+        let percent = |n: &AtomicU64| n.load(Ordering::Relaxed) as f64 / samples * 100.0;
+        if let Some(limit) = self.clamp {
+            println!("clamp {}: {:.3}% of samples", limit, percent(&self.clamped));
+        }
+        if let Some(limit) = self.clamp_indirect {
+            println!("indirect clamp {}: {:.3}% of samples", limit, percent(&self.clamped_indirect));
+        }
+        if self.clamp.is_some() || self.clamp_indirect.is_some() {
+            let removed = self.removed.load(Ordering::Relaxed) as f64 / ENERGY_SCALE;
+            println!("clamped energy: {:.4} per sample", removed / samples);
+        }
+        if let Some(roughness) = self.regularize {
+            println!(
+                "regularize {}: {} of {} specular bounces",
+                roughness,
+                self.regularized.load(Ordering::Relaxed),
+                self.specular.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_keeps_hue_and_counts_energy() {
+        let filter = FireflyFilter::new(Some(2.0), None, None);
+        let c = filter.clamp_sample(Vec3::new(8.0, 4.0, 0.0));
+        assert!((c.x - 2.0).abs() < 1e-12 && (c.y - 1.0).abs() < 1e-12 && c.z == 0.0);
+        let c = filter.clamp_sample(Vec3::new(1.0, 0.5, 0.25));
+        assert_eq!((c.x, c.y, c.z), (1.0, 0.5, 0.25));
+        assert_eq!(filter.samples.load(Ordering::Relaxed), 2);
+        assert_eq!(filter.clamped.load(Ordering::Relaxed), 1);
+        //(12 - 3) / 3 = 3
+        assert_eq!(filter.removed.load(Ordering::Relaxed), (3.0 * ENERGY_SCALE) as u64);
+    }
+
+    #[test]
+    fn negative_components_do_not_hide_removed_energy() {
+        let filter = FireflyFilter::new(Some(1.0), None, None);
+        let c = filter.clamp_sample(Vec3::new(4.0, -6.0, 0.0));
+        assert!((c.x - 1.0).abs() < 1e-12);
+        //正の部分は4から1へ減った
+        assert_eq!(filter.removed.load(Ordering::Relaxed), ENERGY_SCALE as u64);
+    }
+
+    #[test]
+    fn disabled_filter_passes_through() {
+        let filter = FireflyFilter::default();
+        let c = Vec3::new(100.0, 0.0, 0.0);
+        assert_eq!(filter.clamp_sample(c).x, 100.0);
+        assert_eq!(filter.clamp_indirect(c).x, 100.0);
+        assert!(!filter.enabled());
+    }
+}
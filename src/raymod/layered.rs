@@ -0,0 +1,89 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//2つのマテリアルを重みで混ぜる、重みはテクスチャのxでbの割合
+pub struct MixMaterial {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: f64) -> Self {
+        Self::textured(a, b, Arc::new(SolidColor::new(Vec3::new(weight, weight, weight))))
+    }
+    pub fn textured(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: Arc<dyn Texture>) -> Self {
+        Self { a, b, weight }
+    }
+    fn weight(&self, hit: &HitInfo) -> f64 {
+        self.weight.value(hit.u, hit.v, &hit.p).x.clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    //重みの確率でどちらか一方だけを使う
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        if random() < self.weight(hit) {
+            self.b.scatter(ray, hit)
+        } else {
+            self.a.scatter(ray, hit)
+        }
+    }
+    fn emitted(&self, hit: &HitInfo) -> Color {
+        let w = self.weight(hit);
+        self.a.emitted(hit) * (1.0 - w) + self.b.emitted(hit) * w
+    }
+    fn is_specular(&self, hit: &HitInfo) -> bool {
+        self.a.is_specular(hit) && self.b.is_specular(hit)
+    }
+}
+
+//任意の下地に誘電体のコートを重ねる(ニス塗りの木、車の塗装など)
+//コートで反射しなかった光は屈折を無視して下地に渡し、往復分のコートの色を掛ける
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub ior: f64,
+    pub roughness: f64,
+    pub color: Color,
+}
+
+#[allow(dead_code)]
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self { base, ior, roughness, color: Vec3::new(1.0, 1.0, 1.0) }
+    }
+    //色付きのコート、1回通過したときの透過色を与える
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        if !hit.front_face {
+            return self.base.scatter(ray, hit);
+        }
+        let wo = hit.to_local(ray.d.norm() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = Ggx::from_roughness(self.roughness, self.roughness);
+        let h = ggx.sample_vndf(wo);
+        if random() < fresnel_dielectric(wo.dot(&h), self.ior) {
+            let wi = h * (2.0 * wo.dot(&h)) - wo;
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let w = ggx.g(wo, wi) / ggx.g1(wo);
+            return Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), Vec3::new(w, w, w)));
+        }
+        let s = self.base.scatter(ray, hit)?;
+        let tint = self.color.mult(self.color);
+        Some(ScatterInfo::new(s.ray, s.albedo.mult(tint)))
+    }
+    fn emitted(&self, hit: &HitInfo) -> Color {
+        self.base.emitted(hit).mult(self.color)
+    }
+}
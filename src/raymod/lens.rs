@@ -0,0 +1,106 @@
+use crate::raymod::*;
+
+//ISO100、1/125秒、f/8で露出倍率が1になるよう合わせる係数
+const EXPOSURE_CALIBRATION: f64 = 8000.0;
+
+//実在のカメラに合わせた設定、長さはmm、シャッターは秒
+#[derive(Copy, Clone, Debug)]
+pub struct Lens {
+    pub focal_length: f64,
+    pub f_number: f64,
+    //センサーの横幅、縦はアスペクト比から決める
+    pub sensor_width: f64,
+    pub iso: f64,
+    pub shutter: f64,
+    //シーンの1mが何単位か
+    pub units_per_meter: f64,
+}
+
+#[allow(dead_code)]
+impl Lens {
+    //35mmフルサイズ、ISO100、1/125秒
+    pub fn new(focal_length: f64, f_number: f64) -> Self {
+        Self { focal_length, f_number, sensor_width: 36.0, iso: 100.0, shutter: 1.0 / 125.0, units_per_meter: 1.0 }
+    }
+    pub fn with_sensor(mut self, width: f64) -> Self {
+        self.sensor_width = width;
+        self
+    }
+    pub fn with_exposure(mut self, iso: f64, shutter: f64) -> Self {
+        self.iso = iso;
+        self.shutter = shutter;
+        self
+    }
+    pub fn with_units_per_meter(mut self, units_per_meter: f64) -> Self {
+        self.units_per_meter = units_per_meter;
+        self
+    }
+    //縦の画角[度]
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        (2.0 * (sensor_height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+    //有効口径をシーンの単位で
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number * 1e-3 * self.units_per_meter
+    }
+    pub fn exposure(&self) -> f64 {
+        self.iso / 100.0 * self.shutter * EXPOSURE_CALIBRATION / (self.f_number * self.f_number)
+    }
+}
+
+#[allow(dead_code)]
+impl Camera {
+    //シャッターは時刻0から開き、露出時間だけ開いている
+    pub fn physical(lookfrom: Vec3, lookat: Vec3, vup: Vec3, aspect_ratio: f64, lens: &Lens, focus_dist: f64) -> Camera {
+        let mut cam = Camera::new(lookfrom, lookat, vup, lens.vfov(aspect_ratio), aspect_ratio, lens.aperture(), focus_dist)
+            .with_shutter(0.0, lens.shutter);
+        cam.exposure = lens.exposure();
+        cam
+    }
+    //画角を保ったままピント面を動かす
+    pub fn refocus(mut self, focus_dist: f64) -> Camera {
+        let k = focus_dist / self.focus_dist;
+        let center = self.origin - self.w * focus_dist;
+        self.horizontal = self.horizontal * k;
+        self.vertical = self.vertical * k;
+        self.upper_left_corner = center - self.horizontal / 2.0 + self.vertical / 2.0;
+        self.focus_dist = focus_dist;
+        self
+    }
+    //ワールド座標の点にピントを合わせる、距離は光軸方向で測る
+    pub fn focus_on(self, point: Vec3) -> Camera {
+        let d = (self.origin - point).dot(&self.w);
+        if d > EPS { self.refocus(d) } else { self }
+    }
+    //画面上の点(s,t)に写る物にピントを合わせる
+    //projectは実際に使う投影方式、レンズの中心を通りシャッターの開いた時刻のレイで探す
+    pub fn autofocus(self, world: &dyn Shape, s: f64, t: f64, project: impl Fn(Camera) -> Box<dyn CameraModel>) -> Camera {
+        let pinhole = Camera { lens_radius: 0.0, time1: self.time0, ..self.clone() };
+        let probe = project(pinhole).get_ray(s, t);
+        match probe.and_then(|r| world.hit(&r, EPS, INF)).map(|hit| hit.p) {
+            Some(p) => self.focus_on(p),
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn autofocus_uses_the_chosen_projection() {
+        //真横の球は魚眼の円の縁近くに写り、同じ画素の透視投影のレイは外れる
+        let sphere = Sphere::new(Vec3::new(5.0, 0.0, -1.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        let aspect = 16.0 / 9.0;
+        let cam = Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 40.0, aspect, 0.1, 10.0);
+        let s = 0.5 + 0.45 / aspect;
+        let perspective = cam.clone().autofocus(&sphere, s, 0.5, |c| Box::new(c));
+        assert_eq!(perspective.focus_dist, 10.0);
+        let fisheye = cam.autofocus(&sphere, s, 0.5, |c| Box::new(Fisheye::from_camera(c, 180.0, aspect)));
+        assert!(fisheye.focus_dist > 0.0 && fisheye.focus_dist < 2.0, "{}", fisheye.focus_dist);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::raymod::*;
 
 use std::sync::Arc;
-use std::f64::consts::*;
+
+//これより粗さの小さい表面は鏡面とみなす
+pub const SPECULAR_ROUGHNESS: f64 = 0.05;
 
 pub struct ScatterInfo {
     pub ray: Ray,
@@ -11,6 +13,18 @@ pub struct ScatterInfo {
 
 pub trait Material: Sync + Send {
     fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo>;
+    //自己発光、既定では光らない
+    fn emitted(&self, _hit: &HitInfo) -> Color {
+        Color::zero()
+    }
+    //デバッグ出力用の名前、既定では型名
+    fn name(&self) -> &'static str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+    //散乱方向がほぼ1つに決まる鏡面・誘電体ならtrue、パスの正則化で使う
+    fn is_specular(&self, _hit: &HitInfo) -> bool {
+        false
+    }
 }
 
 
@@ -20,10 +34,13 @@ impl ScatterInfo {
     }
 }
 pub struct Lambertian {
-    pub albedo: Vec3,
+    pub albedo: Arc<dyn Texture>,
 }
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
@@ -31,39 +48,142 @@ impl Lambertian {
 impl Material for Lambertian {
     fn scatter(&self, _ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
         let target = hit.p + hit.n + Vec3::random_hemisphere();
-        Some(ScatterInfo::new(Ray::new(hit.p, target - hit.p), self.albedo))
+        Some(ScatterInfo::new(hit.spawn_ray(target - hit.p), self.albedo.value(hit.u, hit.v, &hit.p)))
+    }
+}
+
+//Oren-Nayarの粗い拡散面、sigmaは微小面の傾きの標準偏差[rad]
+pub struct OrenNayar {
+    pub albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Vec3, sigma: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), sigma)
+    }
+    pub fn textured(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let s2 = sigma * sigma;
+        Self { albedo, a: 1.0 - s2 / (2.0 * (s2 + 0.33)), b: 0.45 * s2 / (s2 + 0.09) }
+    }
+}
+
+impl Material for OrenNayar {
+    //cos分布でサンプリングし、Lambertからのずれを重みに掛ける
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let wo = hit.to_local(ray.d.norm() * -1.0);
+        let wi = Vec3::random_cosine_direction();
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let cos_phi = if sin_o > EPS && sin_i > EPS {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        //sin(alpha)tan(beta)、alphaは天頂角の大きい方
+        let (sin_a, tan_b) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs().max(EPS))
+        } else {
+            (sin_i, sin_o / wo.z.abs().max(EPS))
+        };
+        let w = self.a + self.b * cos_phi * sin_a * tan_b;
+        let d = hit.to_world(wi);
+        if d.dot(&hit.ng) <= 0.0 {
+            return None;
+        }
+        Some(ScatterInfo::new(hit.spawn_ray(d), self.albedo.value(hit.u, hit.v, &hit.p) * w))
+    }
+}
+
+//紙や葉のような薄い半透明面、裏側へも拡散して抜ける
+pub struct Translucent {
+    pub reflectance: Arc<dyn Texture>,
+    pub transmittance: Arc<dyn Texture>,
+}
+
+impl Translucent {
+    pub fn new(reflectance: Color, transmittance: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(reflectance)), Arc::new(SolidColor::new(transmittance)))
+    }
+    pub fn textured(reflectance: Arc<dyn Texture>, transmittance: Arc<dyn Texture>) -> Self {
+        Self { reflectance, transmittance }
+    }
+}
+
+impl Material for Translucent {
+    //反射と透過を明るさの比で選び、選んだ側にcos分布で散乱させる
+    fn scatter(&self, _ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let r = self.reflectance.value(hit.u, hit.v, &hit.p);
+        let t = self.transmittance.value(hit.u, hit.v, &hit.p);
+        let (lr, lt) = (r.x + r.y + r.z, t.x + t.y + t.z);
+        if lr + lt <= 0.0 {
+            return None;
+        }
+        let pr = lr / (lr + lt);
+        let mut wi = Vec3::random_cosine_direction();
+        let albedo = if random() < pr {
+            r / pr
+        } else {
+            wi.z = -wi.z;
+            t / (1.0 - pr)
+        };
+        Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), albedo))
     }
 }
 
+//fuzzはテクスチャのx成分を使う
 pub struct Metal {
-    pub albedo:Vec3,
-    pub fuzz:f64,
+    pub albedo:Arc<dyn Texture>,
+    pub fuzz:Arc<dyn Texture>,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3,fuzz:f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), Arc::new(SolidColor::new(Vec3::new(fuzz,fuzz,fuzz))))
+    }
+    pub fn textured(albedo: Arc<dyn Texture>,fuzz:Arc<dyn Texture>) -> Self {
         Self { albedo , fuzz }
     }
 }
 impl Material for Metal {
     fn scatter(&self, _ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let fuzz = self.fuzz.value(hit.u, hit.v, &hit.p).x;
         let mut reflected = _ray.d.norm().reflect(hit.n);
-        reflected = reflected + Vec3::random_hemisphere()*self.fuzz;
-        if reflected.dot(&hit.n)>0.0 {
-            Some(ScatterInfo::new(Ray::new(hit.p, reflected), self.albedo))
+        reflected = reflected + Vec3::random_hemisphere()*fuzz;
+        if reflected.dot(&hit.n)>0.0 && reflected.dot(&hit.ng)>0.0 {
+            Some(ScatterInfo::new(hit.spawn_ray(reflected), self.albedo.value(hit.u, hit.v, &hit.p)))
         }else{
             None
         }        
     }
+    fn is_specular(&self, hit: &HitInfo) -> bool {
+        self.fuzz.value(hit.u, hit.v, &hit.p).x < SPECULAR_ROUGHNESS
+    }
 }
 
+//absorptionは内部を単位長さ進むごとの吸収係数
 pub struct Dielectric {
     pub ri: f64,
+    pub absorption: Vec3,
+    //スペクトルモードで波長ごとの屈折率を使う
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub const fn new(ri: f64) -> Self {
-        Self { ri }
+        Self { ri, absorption: Vec3 { x: 0.0, y: 0.0, z: 0.0 }, dispersion: None }
+    }
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Self { dispersion: Some(dispersion), ..Self::new(dispersion.nd()) }
+    }
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+    //distanceだけ進んだときにcolorの色になる吸収係数を与える
+    pub fn tinted(ri: f64, color: Color, distance: f64) -> Self {
+        Self::new(ri).with_absorption(absorption_from_color(color, distance))
     }
     pub fn schlick(cosine:f64,ri:f64) -> f64 {
         let r0=((1.0-ri)/(1.0+ri)).powi(2);
@@ -71,26 +191,47 @@ impl Dielectric {
     }
 }
 
+pub fn absorption_from_color(color: Color, distance: f64) -> Vec3 {
+    let a = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance;
+    Vec3::new(a(color.x), a(color.y), a(color.z))
+}
+
+//内側から面に当たったレイは媒質中を進んできたのでBeer-Lambertで減衰させる
+pub fn beer_lambert(ray: &Ray, hit: &HitInfo, absorption: Vec3) -> Vec3 {
+    if hit.front_face {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+    let distance = hit.t * ray.d.length().sqrt();
+    Vec3::new((-absorption.x * distance).exp(), (-absorption.y * distance).exp(), (-absorption.z * distance).exp())
+}
+
+//RGBモード(波長0)では固定の屈折率を使う
+pub fn ior_at(ri: f64, dispersion: &Option<Dispersion>, wavelength: f64) -> f64 {
+    match dispersion {
+        Some(d) if wavelength > 0.0 => d.at(wavelength),
+        _ => ri,
+    }
+}
+
 impl Material for Dielectric {
     fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let ri = ior_at(self.ri, &self.dispersion, ray.wavelength);
         let reflected = ray.d.reflect(hit.n);
-        let (outward_normal, ni_over_nt,cosine) = {
-            let dot =  ray.d.dot(&hit.n);
-            if dot > 0.0 {
-                (hit.n*-1.0, self.ri,self.ri * dot / ray.d.length().sqrt() )
-            } else {
-                (hit.n, 1.0/self.ri ,self.ri * dot / ray.d.length().sqrt()*-1.0  )
-            }
+        let cos_i = -ray.d.dot(&hit.n) / ray.d.length().sqrt();
+        //Schlickの近似には屈折率の小さい側の角度を使う
+        let (ni_over_nt,cosine) = if hit.front_face {
+            (1.0/ri, cos_i)
+        } else {
+            (ri, (1.0 - ri*ri*(1.0 - cos_i*cos_i)).max(0.0).sqrt())
         };
-        if let Some(refracted) = (ray.d*-1.0).refract(outward_normal, ni_over_nt) {
-            if Vec3::random_full().x > Self::schlick(cosine,self.ri) {
-                return Some(ScatterInfo::new(Ray::new(hit.p, refracted),
-                                             Vec3::new(1.0,1.0,1.0)))
-            } 
-            
+        let attenuation = beer_lambert(ray, hit, self.absorption);
+        if let Some(refracted) = (ray.d*-1.0).refract(hit.n, ni_over_nt)
+            && Vec3::random_full().x > Self::schlick(cosine,ri) {
+                return Some(ScatterInfo::new(hit.spawn_ray(refracted), attenuation))
         }
-        Some(ScatterInfo::new(Ray::new(hit.p, reflected), Vec3::new(1.0,1.0,1.0)))
+        Some(ScatterInfo::new(hit.spawn_ray(reflected), attenuation))
+    }
+    fn is_specular(&self, _hit: &HitInfo) -> bool {
+        true
     }
 }
-
-
@@ -0,0 +1,232 @@
+use crate::raymod::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+//GGX(Trowbridge-Reitz)分布、ローカル座標(zが法線)で扱う
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+//これより小さいalphaは数値的に扱えないので切り上げる
+const MIN_ALPHA: f64 = 1e-4;
+
+#[allow(dead_code)]
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x: alpha_x.max(MIN_ALPHA), alpha_y: alpha_y.max(MIN_ALPHA) }
+    }
+    //見た目の粗さ(0..1)から、alpha = roughness^2
+    pub fn from_roughness(rx: f64, ry: f64) -> Self {
+        Self::new(rx * rx, ry * ry)
+    }
+    //法線分布関数D(h)
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let x = h.x / self.alpha_x;
+        let y = h.y / self.alpha_y;
+        let e = x * x + y * y + h.z * h.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+    //Smithのマスキング関数の補助項
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let z2 = w.z * w.z;
+        if z2 < 1e-12 {
+            return INF;
+        }
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        0.5 * (-1.0 + (1.0 + a2 / z2).sqrt())
+    }
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+    //高さ相関のマスキング・シャドウイング
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+    //woから見える法線をサンプリングする(Heitz 2018)、wo.z > 0であること
+    pub fn sample_vndf(&self, wo: Vec3) -> Vec3 {
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).norm();
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0.0 { Vec3::new(-vh.y, vh.x, 0.0) / lensq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh % t1;
+        let r = random().sqrt();
+        let phi = 2.0 * PI * random();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).norm()
+    }
+}
+
+//複素屈折率(eta + ik)の導体のフレネル反射率、1チャンネル分
+fn fresnel_conductor_1(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2b2 + cos2;
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor(cos_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    let c = cos_i.clamp(0.0, 1.0);
+    Vec3::new(fresnel_conductor_1(c, eta.x, k.x), fresnel_conductor_1(c, eta.y, k.y), fresnel_conductor_1(c, eta.z, k.z))
+}
+
+//GGXの導体、roughnessはテクスチャのxが接線方向、yが従法線方向
+pub struct Conductor {
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub roughness: Arc<dyn Texture>,
+}
+
+#[allow(dead_code)]
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f64) -> Self {
+        Self::anisotropic(eta, k, roughness, roughness)
+    }
+    pub fn anisotropic(eta: Vec3, k: Vec3, roughness_u: f64, roughness_v: f64) -> Self {
+        Self::textured(eta, k, Arc::new(SolidColor::new(Vec3::new(roughness_u, roughness_v, 0.0))))
+    }
+    pub fn textured(eta: Vec3, k: Vec3, roughness: Arc<dyn Texture>) -> Self {
+        Self { eta, k, roughness }
+    }
+    //RGBの代表波長での測定値
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603), roughness)
+    }
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142), roughness)
+    }
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837), roughness)
+    }
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147), roughness)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let wo = hit.to_local(ray.d.norm() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let r = self.roughness.value(hit.u, hit.v, &hit.p);
+        let ggx = Ggx::from_roughness(r.x, r.y);
+        let h = ggx.sample_vndf(wo);
+        let wi = h * (2.0 * wo.dot(&h)) - wo;
+        if wi.z <= 0.0 {
+            return None;
+        }
+        //VNDFサンプリングではD・pdfが打ち消し合い F*G2/G1 が残る
+        let weight = fresnel_conductor(wo.dot(&h), self.eta, self.k) * (ggx.g(wo, wi) / ggx.g1(wo));
+        let d = hit.to_world(wi);
+        if d.dot(&hit.ng) <= 0.0 {
+            return None;
+        }
+        Some(ScatterInfo::new(hit.spawn_ray(d), weight))
+    }
+    fn is_specular(&self, hit: &HitInfo) -> bool {
+        self.roughness.value(hit.u, hit.v, &hit.p).x < SPECULAR_ROUGHNESS
+    }
+}
+
+//誘電体のフレネル反射率、etaは透過側/入射側の屈折率比
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+//マイクロファセット法線hでwoを屈折させる、全反射ならNone
+pub fn refract_microfacet(wo: Vec3, h: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(&h);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(wo * (-1.0 / eta) + h * (cos_i / eta - cos_t))
+}
+
+//GGXの粗い誘電体(磨りガラス)、反射と屈折をフレネル項で選ぶ
+pub struct RoughDielectric {
+    pub ri: f64,
+    pub roughness: Arc<dyn Texture>,
+    pub absorption: Vec3,
+    pub dispersion: Option<Dispersion>,
+}
+
+impl RoughDielectric {
+    pub fn new(ri: f64, roughness: f64) -> Self {
+        Self::textured(ri, Arc::new(SolidColor::new(Vec3::new(roughness, roughness, 0.0))))
+    }
+    pub fn textured(ri: f64, roughness: Arc<dyn Texture>) -> Self {
+        Self { ri, roughness, absorption: Vec3::zero(), dispersion: None }
+    }
+    #[allow(dead_code)]
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ri = dispersion.nd();
+        self.dispersion = Some(dispersion);
+        self
+    }
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let wo = hit.to_local(ray.d.norm() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let r = self.roughness.value(hit.u, hit.v, &hit.p);
+        let ggx = Ggx::from_roughness(r.x, r.y);
+        let h = ggx.sample_vndf(wo);
+        let ri = ior_at(self.ri, &self.dispersion, ray.wavelength);
+        let eta = if hit.front_face { ri } else { 1.0 / ri };
+        let f = fresnel_dielectric(wo.dot(&h), eta);
+        //反射・屈折をFの確率で選ぶので重みにはFが残らない
+        let wi = match refract_microfacet(wo, h, eta) {
+            Some(t) if random() >= f => {
+                if t.z >= 0.0 {
+                    return None;
+                }
+                t
+            }
+            _ => {
+                let reflected = h * (2.0 * wo.dot(&h)) - wo;
+                if reflected.z <= 0.0 {
+                    return None;
+                }
+                reflected
+            }
+        };
+        let weight = beer_lambert(ray, hit, self.absorption) * (ggx.g(wo, wi) / ggx.g1(wo));
+        Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), weight))
+    }
+    fn is_specular(&self, hit: &HitInfo) -> bool {
+        self.roughness.value(hit.u, hit.v, &hit.p).x < SPECULAR_ROUGHNESS
+    }
+}
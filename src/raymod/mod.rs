@@ -2,12 +2,52 @@ mod vec3;
 mod rayunit;
 mod optarg;
 mod material;
+mod denoise;
+mod texture;
+mod normalmap;
+mod aabb;
+mod rect;
+mod primitive;
+mod transform;
+mod volume;
+mod csg;
+mod sdf;
+mod microfacet;
+mod principled;
+mod layered;
+mod spectral;
+mod lens;
+mod camera;
+mod bokeh;
+mod animation;
+mod firefly;
 pub use self::vec3::*;
 pub use self::rayunit::*;
 pub use self::optarg::*;
 pub use self::material::*;
+pub use self::denoise::*;
+pub use self::texture::*;
+pub use self::normalmap::*;
+pub use self::aabb::*;
+pub use self::rect::*;
+pub use self::primitive::*;
+pub use self::transform::*;
+pub use self::volume::*;
+pub use self::csg::*;
+pub use self::sdf::*;
+pub use self::microfacet::*;
+pub use self::principled::*;
+pub use self::layered::*;
+pub use self::spectral::*;
+pub use self::lens::*;
+pub use self::camera::*;
+pub use self::bokeh::*;
+pub use self::animation::*;
+pub use self::firefly::*;
 
 pub const EPS: f64 = 1e-6;
 pub const INF: f64 = 1e20;
+pub const RAY_OFFSET: f64 = 1e-5;
+#[allow(dead_code)]
 pub const FRAC_SQRT_3: f64 = 1.732050807568877293527446341505872367;
 
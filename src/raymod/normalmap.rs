@@ -0,0 +1,88 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//シェーディング法線を摂動させる
+pub trait NormalModifier: Sync + Send {
+    fn normal(&self, hit: &HitInfo) -> Vec3;
+}
+
+//接空間の法線マップ、RGBをそれぞれ(tangent,bitangent,n)方向の成分とする
+pub struct NormalMap {
+    pub image: Arc<ImageTexture>,
+    pub strength: f64,
+}
+
+impl NormalMap {
+    pub fn new(image: Arc<ImageTexture>, strength: f64) -> Self {
+        Self { image, strength }
+    }
+    pub fn load(filename: &str, strength: f64) -> Result<Self, image::ImageError> {
+        Ok(Self::new(Arc::new(ImageTexture::load_data(filename, WrapMode::Repeat)?), strength))
+    }
+}
+
+impl NormalModifier for NormalMap {
+    fn normal(&self, hit: &HitInfo) -> Vec3 {
+        let c = self.image.sample(hit.u, hit.v);
+        let local = Vec3::new(
+            (c.x * 2.0 - 1.0) * self.strength,
+            (c.y * 2.0 - 1.0) * self.strength,
+            (c.z * 2.0 - 1.0).max(EPS),
+        );
+        hit.to_world(local)
+    }
+}
+
+//高さテクスチャ(x成分)の差分から法線を求めるバンプマップ
+pub struct BumpMap {
+    pub height: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+const BUMP_DELTA: f64 = 1e-3;
+
+impl BumpMap {
+    pub fn new(height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { height, scale }
+    }
+}
+
+impl NormalModifier for BumpMap {
+    fn normal(&self, hit: &HitInfo) -> Vec3 {
+        let h = self.height.value(hit.u, hit.v, &hit.p).x;
+        //UVでも空間座標でも評価できるよう、両方を同時にずらす
+        let hu = self.height.value(hit.u + BUMP_DELTA, hit.v, &(hit.p + hit.dpdu * BUMP_DELTA)).x;
+        let hv = self.height.value(hit.u, hit.v + BUMP_DELTA, &(hit.p + hit.dpdv * BUMP_DELTA)).x;
+        let dhdu = (hu - h) / BUMP_DELTA * self.scale;
+        let dhdv = (hv - h) / BUMP_DELTA * self.scale;
+
+        let dpdu = hit.dpdu + hit.n * dhdu;
+        let dpdv = hit.dpdv + hit.n * dhdv;
+        let n = (dpdu % dpdv).norm();
+        if n.dot(&hit.n) < 0.0 { n * -1.0 } else { n }
+    }
+}
+
+//任意のマテリアルのシェーディング法線を差し替えるラッパー
+pub struct Bumped {
+    pub base: Arc<dyn Material>,
+    pub normal: Arc<dyn NormalModifier>,
+}
+
+impl Bumped {
+    pub fn new(base: Arc<dyn Material>, normal: Arc<dyn NormalModifier>) -> Self {
+        Self { base, normal }
+    }
+}
+
+impl Material for Bumped {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let mut bumped = hit.clone();
+        bumped.set_shading_normal(self.normal.normal(hit));
+        self.base.scatter(ray, &bumped)
+    }
+    fn is_specular(&self, hit: &HitInfo) -> bool {
+        self.base.is_specular(hit)
+    }
+}
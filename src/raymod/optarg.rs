@@ -1,3 +1,4 @@
+use crate::raymod::{Interpolation, Lens};
 use getopts::Options;
 use std::process;
 
@@ -9,6 +10,23 @@ pub struct Args {
     pub w:usize,
     pub m:usize,
     pub output: String,
+    pub denoise: bool,
+    pub spectral: bool,
+    pub lens: Option<Lens>,
+    pub focus: Option<(usize, usize)>,
+    pub camera: String,
+    pub interocular: Option<f64>,
+    pub bokeh: Option<String>,
+    pub cat_eye: f64,
+    pub chromatic: (f64, f64),
+    pub frames: Option<(usize, usize)>,
+    pub keys: Option<String>,
+    pub interpolation: Interpolation,
+    pub region: Option<(usize, usize, usize, usize)>,
+    pub pixel: Option<(usize, usize)>,
+    pub clamp: Option<f64>,
+    pub clamp_indirect: Option<f64>,
+    pub regularize: Option<f64>,
 }
 
 fn print_usage(exe_name: &str, opts: &Options) {
@@ -17,16 +35,46 @@ fn print_usage(exe_name: &str, opts: &Options) {
     process::exit(0);
 }
 
+//不正なオプションの理由とヘルプを出して終了
+fn usage_error(exe_name: &str, opts: &Options, msg: &str) -> ! {
+    eprintln!("{}", msg);
+    let brief = format!("Usage: {}  [Options]", exe_name);
+    eprint!("{}", opts.usage(&brief));
+    process::exit(1);
+}
+
+//カンマ区切りの値、どれか1つでも読めなければNone
+fn list<T: std::str::FromStr>(s: &str) -> Option<Vec<T>> {
+    s.split(',').map(|x| x.trim().parse().ok()).collect()
+}
+
 pub fn parameters() -> Args {
     // コマンドラインオプションを取得
     let args: Vec<String> = std::env::args().collect();
 
     // キーワード引数を指定
     let mut opts = Options::new();
-    opts.optopt("s", "samples", "sampling number", "1..etc");
-    opts.optopt("w","width","screen width","ex)640");
-    opts.optopt("m","model","model number","0..9");
+    opts.optopt("s", "samples", "sampling number (default 128)", "1..etc");
+    opts.optopt("w","width","screen width (default 384)","ex)640");
+    opts.optopt("m","model","model number 0:random 1:texture 2:earth 3:box 4:primitive 5:instance 6:bouncing 7:smoke 8:cloud 9:csg 10:sdf 11:material 12:prism","0..12");
     opts.optopt("o", "output", "set output file name", "[FILE]");
+    opts.optflag("d", "denoise", "denoise with albedo/normal/depth buffers");
+    opts.optflag("", "spectral", "trace one wavelength per sample for dispersion");
+    opts.optopt("", "lens", "physical camera: focal length[mm], f-number, ISO, shutter[s]", "50,2.8[,100,0.008]");
+    opts.optopt("", "focus", "autofocus on the object seen at this pixel", "X,Y");
+    opts.optopt("c", "camera", "projection: perspective, ortho, fisheye, equirect, stereo", "NAME");
+    opts.optopt("", "interocular", "eye separation of the stereo camera (default focus distance / 30)", "0.2");
+    opts.optopt("", "bokeh", "aperture: blade count and rotation[deg], or a mask image", "6[,15]|FILE");
+    opts.optopt("", "cat-eye", "clip the aperture toward the image edges, darkening them (0: off)", "0.5");
+    opts.optopt("", "chromatic", "lateral and axial chromatic aberration (spectral only)", "0.01,0.02");
+    opts.optopt("", "frames", "render a numbered sequence (turntable unless --keys is given)", "1..120");
+    opts.optopt("", "keys", "camera keyframes: time lookfrom lookat vfov [focus] per line", "FILE");
+    opts.optopt("", "interp", "keyframe interpolation: linear, spline", "NAME");
+    opts.optopt("", "region", "render only this window, the rest stays black", "X0,Y0,X1,Y1");
+    opts.optopt("", "pixel", "print every bounce of this pixel's samples instead of rendering", "X,Y");
+    opts.optopt("", "clamp", "limit the radiance of each sample", "10");
+    opts.optopt("", "clamp-indirect", "limit only light arriving after two or more bounces", "3");
+    opts.optopt("", "regularize", "roughen specular bounces after the first one", "0.2");
     opts.optflag("h", "help", "print this help");
 
     // パース
@@ -42,22 +90,99 @@ pub fn parameters() -> Args {
 //    if matches.free.is_empty() {print_usage(&args[0], &opts); }
 
     // キーワード引数の取得
-    let sampstr  = matches.opt_str("s").unwrap_or("1".to_string());
+    let sampstr  = matches.opt_str("s").unwrap_or("128".to_string());
     let s:usize =sampstr.parse().unwrap();
-    let w  = matches.opt_str("w").unwrap_or("640".to_string()).parse().unwrap();
+    let w  = matches.opt_str("w").unwrap_or("384".to_string()).parse().unwrap();
     let m  = matches.opt_str("m").unwrap_or("0".to_string()).parse().unwrap();
     let output = matches.opt_str("o").unwrap_or("image.png".to_string());
+    let denoise = matches.opt_present("d");
+    let spectral = matches.opt_present("spectral");
+    let lens = matches.opt_str("lens").map(|s| match list::<f64>(&s).as_deref() {
+        Some(&[focal, f_number]) => Lens::new(focal, f_number),
+        Some(&[focal, f_number, iso, shutter]) => Lens::new(focal, f_number).with_exposure(iso, shutter),
+        _ => usage_error(&args[0], &opts, &format!("--lens expects 2 or 4 numbers: {}", s)),
+    });
+    let camera = matches.opt_str("c").unwrap_or("perspective".to_string());
+    if !["perspective", "ortho", "fisheye", "equirect", "stereo"].contains(&camera.as_str()) {
+        usage_error(&args[0], &opts, &format!("unknown camera: {}", camera));
+    }
+    let interocular = matches.opt_str("interocular").map(|s| match s.trim().parse::<f64>() {
+        Ok(d) if d >= 0.0 => d,
+        _ => usage_error(&args[0], &opts, &format!("--interocular expects a distance: {}", s)),
+    });
+    let focus = matches.opt_str("focus").map(|s| match list::<usize>(&s).as_deref() {
+        Some(&[x, y]) => (x, y),
+        _ => usage_error(&args[0], &opts, &format!("--focus expects X,Y: {}", s)),
+    });
+    let bokeh = matches.opt_str("bokeh");
+    let cat_eye = matches.opt_str("cat-eye").map_or(0.0, |s| match s.trim().parse::<f64>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => x,
+        _ => usage_error(&args[0], &opts, &format!("--cat-eye expects a number >= 0: {}", s)),
+    });
+    let chromatic = matches.opt_str("chromatic").map_or((0.0, 0.0), |s| match list::<f64>(&s).as_deref() {
+        Some(&[lateral]) if lateral.is_finite() => (lateral, 0.0),
+        Some(&[lateral, axial]) if lateral.is_finite() && axial.is_finite() => (lateral, axial),
+        _ => usage_error(&args[0], &opts, &format!("--chromatic expects 1 or 2 numbers: {}", s)),
+    });
+    let frames = matches.opt_str("frames").map(|s| {
+        let (a, b) = s.split_once("..").unwrap_or((&s, &s));
+        match (a.trim().parse::<usize>(), b.trim_start_matches('=').trim().parse::<usize>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end),
+            _ => usage_error(&args[0], &opts, &format!("--frames expects START..END with START <= END: {}", s)),
+        }
+    });
+    let keys = matches.opt_str("keys");
+    let interpolation = match matches.opt_str("interp").as_deref() {
+        Some("linear") => Interpolation::Linear,
+        Some("spline") | None => Interpolation::CatmullRom,
+        Some(name) => usage_error(&args[0], &opts, &format!("unknown interpolation: {}", name)),
+    };
+    //画像の大きさとの比較はmainで行う
+    let region = matches.opt_str("region").map(|s| match list::<usize>(&s).as_deref() {
+        Some(&[x0, y0, x1, y1]) if x0 < x1 && y0 < y1 => (x0, y0, x1, y1),
+        _ => usage_error(&args[0], &opts, &format!("--region expects X0,Y0,X1,Y1 with X0 < X1 and Y0 < Y1: {}", s)),
+    });
+    let pixel = matches.opt_str("pixel").map(|s| match list::<usize>(&s).as_deref() {
+        Some(&[x, y]) => (x, y),
+        _ => usage_error(&args[0], &opts, &format!("--pixel expects X,Y: {}", s)),
+    });
+    //上限と粗さは正の有限値だけ、負の上限では色の符号が反転する
+    let positive = |name: &str| {
+        matches.opt_str(name).map(|s| match s.trim().parse::<f64>() {
+            Ok(x) if x.is_finite() && x > 0.0 => x,
+            _ => usage_error(&args[0], &opts, &format!("--{} expects a positive number: {}", name, s)),
+        })
+    };
+    let clamp = positive("clamp");
+    let clamp_indirect = positive("clamp-indirect");
+    let regularize = positive("regularize");
     // 位置引数の取得
 //    let repeat = matches.free[0].clone().parse::<usize>().unwrap_or_else(|f| panic!("{}",f.to_string()));
 
     // 構造体の生成
-    let ret = Args {
+    Args {
         s,
         w,
         m,
         output,
-    };
-    return ret;
+        denoise,
+        spectral,
+        lens,
+        focus,
+        camera,
+        interocular,
+        bokeh,
+        cat_eye,
+        chromatic,
+        frames,
+        keys,
+        interpolation,
+        region,
+        pixel,
+        clamp,
+        clamp_indirect,
+        regularize,
+    }
 }
 #[allow(dead_code)]
 fn test() {
@@ -0,0 +1,501 @@
+use crate::raymod::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+//軸方向を指定する形状のローカル座標系、zが軸
+#[derive(Clone, Debug)]
+struct Frame {
+    origin: Vec3,
+    t: Vec3,
+    b: Vec3,
+    a: Vec3,
+}
+
+impl Frame {
+    fn new(origin: Vec3, axis: Vec3) -> Self {
+        let a = axis.norm();
+        let (t, b) = a.orthonormal_basis();
+        Self { origin, t, b, a }
+    }
+    fn to_local(&self, p: Vec3) -> Vec3 {
+        Vec3::new(p.dot(&self.t), p.dot(&self.b), p.dot(&self.a))
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        self.t * v.x + self.b * v.y + self.a * v.z
+    }
+    fn ray_to_local(&self, r: &Ray) -> (Vec3, Vec3) {
+        (self.to_local(r.o - self.origin), self.to_local(r.d))
+    }
+    //ローカル座標の点群を囲むワールド座標の境界ボックス
+    fn bounds(&self, lo: Vec3, hi: Vec3) -> Aabb {
+        let mut bbox = Aabb::new(self.origin + self.to_world(lo), self.origin + self.to_world(lo));
+        for i in 0..8 {
+            let c = Vec3::new(
+                if i & 1 == 0 { lo.x } else { hi.x },
+                if i & 2 == 0 { lo.y } else { hi.y },
+                if i & 4 == 0 { lo.z } else { hi.z },
+            );
+            let p = self.origin + self.to_world(c);
+            bbox = bbox.surrounding(&Aabb::new(p, p));
+        }
+        bbox
+    }
+}
+
+//無限平面、UVはワールド単位の平面座標
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        let frame = Frame::new(point, normal);
+        Self { point, normal: frame.a, material, frame }
+    }
+}
+
+impl Shape for Plane {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let denom = self.normal.dot(&r.d);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.point - r.o).dot(&self.normal) / denom;
+        if !(t > t0 && t < t1) {
+            return None;
+        }
+        let mut info = HitInfo::new(r, t, self.normal, Arc::clone(&self.material), self);
+        let lp = self.frame.to_local(info.p - self.point);
+        info.set_uv(lp.x, lp.y);
+        info.set_derivatives(self.frame.t, self.frame.b);
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+//円板、Uは角度、Vは中心からの距離
+pub struct Disk {
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        let frame = Frame::new(center, normal);
+        Self { normal: frame.a, radius, material, frame }
+    }
+}
+
+impl Shape for Disk {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let (o, d) = self.frame.ray_to_local(r);
+        let (t, x, y) = hit_cap(o, d, 0.0, self.radius, t0, t1)?;
+        let mut info = HitInfo::new(r, t, self.normal, Arc::clone(&self.material), self);
+        let (u, v, dpdu, dpdv) = polar_uv(x, y, self.radius);
+        info.set_uv(u, v);
+        info.set_derivatives(self.frame.to_world(dpdu), self.frame.to_world(dpdv));
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(self.frame.bounds(Vec3::new(-r, -r, 0.0), Vec3::new(r, r, 0.0)).pad(1e-4))
+    }
+}
+
+//ローカル座標でz=hの円板との交差、(t,x,y)を返す
+fn hit_cap(o: Vec3, d: Vec3, h: f64, radius: f64, t0: f64, t1: f64) -> Option<(f64, f64, f64)> {
+    if d.z.abs() < 1e-12 {
+        return None;
+    }
+    let t = (h - o.z) / d.z;
+    if !(t > t0 && t < t1) {
+        return None;
+    }
+    let (x, y) = (o.x + d.x * t, o.y + d.y * t);
+    if x * x + y * y > radius * radius {
+        return None;
+    }
+    Some((t, x, y))
+}
+
+//円板上の点(x,y)の極座標UVとローカル座標での微分
+fn polar_uv(x: f64, y: f64, radius: f64) -> (f64, f64, Vec3, Vec3) {
+    let rho = (x * x + y * y).sqrt();
+    let phi = y.atan2(x);
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+    let (sin_p, cos_p) = phi.sin_cos();
+    let dpdu = Vec3::new(-sin_p, cos_p, 0.0) * (2.0 * PI * rho);
+    let dpdv = Vec3::new(cos_p, sin_p, 0.0) * radius;
+    (phi / (2.0 * PI), rho / radius, dpdu, dpdv)
+}
+
+//側面のUV、Uは軸回りの角度、Vは高さ
+fn side_uv(x: f64, y: f64, z: f64, height: f64) -> (f64, f64) {
+    let phi = y.atan2(x);
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+    (phi / (2.0 * PI), z / height)
+}
+
+//ax^2+bx+c=0の実数解を小さい順に
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        let x = -c / b;
+        return Some((x, x));
+    }
+    let d = b * b - 4.0 * a * c;
+    if d < 0.0 {
+        return None;
+    }
+    //桁落ちしない形
+    let q = -0.5 * (b + b.signum() * d.sqrt());
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some(if x0 < x1 { (x0, x1) } else { (x1, x0) })
+}
+
+//底面の中心base、軸方向axis、半径radius、高さheightの円柱
+pub struct Cylinder {
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, capped: bool, material: Arc<dyn Material>) -> Self {
+        let frame = Frame::new(base, axis);
+        Self { radius, height, capped, material, frame }
+    }
+}
+
+impl Shape for Cylinder {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let (o, d) = self.frame.ray_to_local(r);
+        let mut closest = t1;
+        let mut result: Option<HitInfo> = None;
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        if let Some((ta, tb)) = solve_quadratic(a, b, c) {
+            for t in [ta, tb] {
+                let z = o.z + d.z * t;
+                if t > t0 && t < closest && (0.0..=self.height).contains(&z) {
+                    let (x, y) = (o.x + d.x * t, o.y + d.y * t);
+                    let n = self.frame.to_world(Vec3::new(x, y, 0.0) / self.radius);
+                    let mut info = HitInfo::new(r, t, n, Arc::clone(&self.material), self);
+                    let (u, v) = side_uv(x, y, z, self.height);
+                    info.set_uv(u, v);
+                    info.set_derivatives(
+                        self.frame.to_world(Vec3::new(-y, x, 0.0) * (2.0 * PI)),
+                        self.frame.to_world(Vec3::new(0.0, 0.0, self.height)),
+                    );
+                    closest = t;
+                    result = Some(info);
+                    break;
+                }
+            }
+        }
+        if self.capped {
+            for (h, nz) in [(0.0, -1.0), (self.height, 1.0)] {
+                if let Some((t, x, y)) = hit_cap(o, d, h, self.radius, t0, closest) {
+                    let mut info = HitInfo::new(r, t, self.frame.a * nz, Arc::clone(&self.material), self);
+                    let (u, v, dpdu, dpdv) = polar_uv(x, y, self.radius);
+                    info.set_uv(u, v);
+                    info.set_derivatives(self.frame.to_world(dpdu), self.frame.to_world(dpdv));
+                    closest = t;
+                    result = Some(info);
+                }
+            }
+        }
+        result
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(self.frame.bounds(Vec3::new(-r, -r, 0.0), Vec3::new(r, r, self.height)))
+    }
+}
+
+//底面の中心base、軸方向axis、底面半径radius、高さheightの円錐、頂点は軸の先
+pub struct Cone {
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, capped: bool, material: Arc<dyn Material>) -> Self {
+        let frame = Frame::new(base, axis);
+        Self { radius, height, capped, material, frame }
+    }
+}
+
+impl Shape for Cone {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let (o, d) = self.frame.ray_to_local(r);
+        let mut closest = t1;
+        let mut result: Option<HitInfo> = None;
+
+        //x^2+y^2 = k^2 (h-z)^2
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let hz = self.height - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * hz * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * hz * hz;
+        if let Some((ta, tb)) = solve_quadratic(a, b, c) {
+            for t in [ta, tb] {
+                let z = o.z + d.z * t;
+                if t > t0 && t < closest && (0.0..=self.height).contains(&z) {
+                    let (x, y) = (o.x + d.x * t, o.y + d.y * t);
+                    let n = self.frame.to_world(Vec3::new(x, y, k2 * (self.height - z)).norm());
+                    let mut info = HitInfo::new(r, t, n, Arc::clone(&self.material), self);
+                    let (u, v) = side_uv(x, y, z, self.height);
+                    info.set_uv(u, v);
+                    //頂点に向かって半径が縮む
+                    let phi = u * 2.0 * PI;
+                    info.set_derivatives(
+                        self.frame.to_world(Vec3::new(-y, x, 0.0) * (2.0 * PI)),
+                        self.frame.to_world(Vec3::new(-self.radius * phi.cos(), -self.radius * phi.sin(), self.height)),
+                    );
+                    closest = t;
+                    result = Some(info);
+                    break;
+                }
+            }
+        }
+        if self.capped
+            && let Some((t, x, y)) = hit_cap(o, d, 0.0, self.radius, t0, closest) {
+                let mut info = HitInfo::new(r, t, self.frame.a * -1.0, Arc::clone(&self.material), self);
+                let (u, v, dpdu, dpdv) = polar_uv(x, y, self.radius);
+                info.set_uv(u, v);
+                info.set_derivatives(self.frame.to_world(dpdu), self.frame.to_world(dpdv));
+                result = Some(info);
+        }
+        result
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(self.frame.bounds(Vec3::new(-r, -r, 0.0), Vec3::new(r, r, self.height)))
+    }
+}
+
+//中心center、軸axis、中心円の半径major、管の半径minorのトーラス
+pub struct Torus {
+    pub major: f64,
+    pub minor: f64,
+    pub material: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major: f64, minor: f64, material: Arc<dyn Material>) -> Self {
+        let frame = Frame::new(center, axis);
+        Self { major, minor, material, frame }
+    }
+}
+
+impl Shape for Torus {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let (o, d) = self.frame.ray_to_local(r);
+        //境界球で早期判定し、精度のため始点を境界球の手前まで進める
+        let bound = self.major + self.minor;
+        let dl = d.length().sqrt();
+        let dn = d / dl;
+        let tca = -o.dot(&dn);
+        let d2 = o.length() - tca * tca;
+        if d2 > bound * bound {
+            return None;
+        }
+        let shift = (tca - (bound * bound - d2).sqrt()).max(0.0);
+        let os = o + dn * shift;
+
+        //(|p|^2 + R^2 - r^2)^2 = 4R^2 (x^2+y^2) を |d|=1 の s について解く
+        let (rr, r2) = (self.major * self.major, self.minor * self.minor);
+        let od = os.dot(&dn);
+        let oo = os.length();
+        let k = oo + rr - r2;
+        let c3 = 4.0 * od;
+        let c2 = 2.0 * k + 4.0 * od * od - 4.0 * rr * (dn.x * dn.x + dn.y * dn.y);
+        let c1 = 4.0 * k * od - 8.0 * rr * (os.x * dn.x + os.y * dn.y);
+        let c0 = k * k - 4.0 * rr * (os.x * os.x + os.y * os.y);
+
+        //かすめるレイや退化した係数ではNaNが出るので捨てる
+        let mut roots = solve_quartic(c3, c2, c1, c0);
+        roots.retain(|s| s.is_finite());
+        roots.sort_by(f64::total_cmp);
+        for s in roots {
+            let t = (s + shift) / dl;
+            if t > t0 && t < t1 {
+                let p = o + d * t;
+                let rho = (p.x * p.x + p.y * p.y).sqrt();
+                //軸上(管が軸まで届くトーラス)では中心円の向きをx軸に決める
+                let (cx, cy) = if rho > EPS { (p.x / rho, p.y / rho) } else { (1.0, 0.0) };
+                let q = Vec3::new(cx, cy, 0.0) * self.major;
+                let n = self.frame.to_world((p - q) / self.minor);
+                let mut info = HitInfo::new(r, t, n, Arc::clone(&self.material), self);
+                let phi = p.y.atan2(p.x);
+                let theta = p.z.atan2(rho - self.major);
+                let u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+                let v = if theta < 0.0 { theta + 2.0 * PI } else { theta } / (2.0 * PI);
+                info.set_uv(u, v);
+                let dpdu = Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI);
+                let (sin_t, cos_t) = theta.sin_cos();
+                let dpdv = Vec3::new(-sin_t * cx, -sin_t * cy, cos_t) * (2.0 * PI * self.minor);
+                info.set_derivatives(self.frame.to_world(dpdu), self.frame.to_world(dpdv));
+                return Some(info);
+            }
+        }
+        None
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (r, m) = (self.major + self.minor, self.minor);
+        Some(self.frame.bounds(Vec3::new(-r, -r, -m), Vec3::new(r, r, m)))
+    }
+}
+
+//x^3+ax^2+bx+c=0の実数解
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let q3 = q * q * q;
+    if r * r < q3 {
+        let theta = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        let sq = -2.0 * q.sqrt();
+        vec![
+            sq * (theta / 3.0).cos() - a / 3.0,
+            sq * ((theta + 2.0 * PI) / 3.0).cos() - a / 3.0,
+            sq * ((theta - 2.0 * PI) / 3.0).cos() - a / 3.0,
+        ]
+    } else {
+        let e = -r.signum() * (r.abs() + (r * r - q3).sqrt()).cbrt();
+        let f = if e == 0.0 { 0.0 } else { q / e };
+        vec![e + f - a / 3.0]
+    }
+}
+
+//x^4+ax^3+bx^2+cx+d=0の実数解 (Ferrari法、Newton法で仕上げ)
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    //x = y - a/4 で3次の項を消す
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut ys = Vec::with_capacity(4);
+    if q.abs() < 1e-12 {
+        //複二次式
+        if let Some((z0, z1)) = solve_quadratic(1.0, p, r) {
+            for z in [z0, z1] {
+                if z >= 0.0 {
+                    ys.push(z.sqrt());
+                    ys.push(-z.sqrt());
+                }
+            }
+        }
+    } else {
+        //分解方程式 8m^3 + 8pm^2 + (2p^2-8r)m - q^2 = 0 の正の解
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::MIN, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        for (sign, qs) in [(1.0, -q / (2.0 * s)), (-1.0, q / (2.0 * s))] {
+            if let Some((y0, y1)) = solve_quadratic(1.0, sign * s, p / 2.0 + m + qs) {
+                ys.push(y0);
+                ys.push(y1);
+            }
+        }
+    }
+    ys.into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let f = (((x + a) * x + b) * x + c) * x + d;
+                let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+                if df.abs() > 1e-12 {
+                    x -= f / df;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut v: Vec<f64>) -> Vec<f64> {
+        v.sort_by(f64::total_cmp);
+        v
+    }
+
+    #[test]
+    fn quartic_four_real_roots() {
+        //(x-1)(x-2)(x-3)(x-4)
+        let roots = sorted(solve_quartic(-10.0, 35.0, -50.0, 24.0));
+        assert_eq!(roots.len(), 4);
+        for (r, e) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((r - e).abs() < 1e-6, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quartic_biquadratic() {
+        //(x^2-1)(x^2-4)
+        let roots = sorted(solve_quartic(0.0, -5.0, 0.0, 4.0));
+        assert_eq!(roots.len(), 4);
+        for (r, e) in roots.iter().zip([-2.0, -1.0, 1.0, 2.0]) {
+            assert!((r - e).abs() < 1e-6, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quartic_no_real_roots() {
+        //x^4+1
+        assert!(solve_quartic(0.0, 0.0, 0.0, 1.0).is_empty());
+    }
+
+    fn torus(major: f64, minor: f64) -> Torus {
+        Torus::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), major, minor, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn torus_hit_outer_rim() {
+        let t = torus(2.0, 0.5);
+        let r = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = t.hit(&r, EPS, INF).unwrap();
+        assert!((hit.t - 7.5).abs() < 1e-6);
+        assert!((hit.n.x + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn torus_grazing_and_axis_rays() {
+        //管の上端をかすめるレイ、軸に沿うレイでもパニックせず法線は有限
+        let t = torus(2.0, 0.5);
+        let graze = Ray::new(Vec3::new(-10.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        if let Some(hit) = t.hit(&graze, EPS, INF) {
+            assert!(hit.n.x.is_finite() && hit.n.y.is_finite() && hit.n.z.is_finite());
+        }
+        let spindle = torus(0.5, 1.0);
+        let axis = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = spindle.hit(&axis, EPS, INF).unwrap();
+        assert!(hit.n.x.is_finite() && hit.n.y.is_finite() && hit.n.z.is_finite());
+    }
+}
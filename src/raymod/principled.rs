@@ -0,0 +1,284 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//Disney/glTF風の統合マテリアル
+//上からクリアコート、金属、透過、誘電体の鏡面反射、拡散+シーンの順に層を重ね、
+//各層は反射率に応じた確率で選ぶので全体のエネルギーは1を超えない
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    //glTFと同じくyがroughness、zがmetallicの係数になる
+    pub metallic_roughness: Arc<dyn Texture>,
+    pub metallic: f64,
+    pub roughness: f64,
+    //誘電体の鏡面反射の強さ、0.5でF0=0.04
+    pub specular: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub sheen: f64,
+    //0で白、1でベースカラーの色味
+    pub sheen_tint: f64,
+    //指定するとsheen_tintの代わりにこの色を使う
+    pub sheen_color: Option<Color>,
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+//glTF 2.0のpbrMetallicRoughnessと拡張(clearcoat, sheen, transmission, ior)のパラメータ
+pub struct GltfPbr {
+    pub base_color_factor: Color,
+    pub base_color_texture: Option<Arc<dyn Texture>>,
+    pub metallic_factor: f64,
+    pub roughness_factor: f64,
+    pub metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    pub clearcoat_factor: f64,
+    pub clearcoat_roughness_factor: f64,
+    pub sheen_color_factor: Color,
+    pub transmission_factor: f64,
+    pub ior: f64,
+}
+
+impl Default for GltfPbr {
+    fn default() -> Self {
+        Self {
+            base_color_factor: Vec3::new(1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            sheen_color_factor: Vec3::zero(),
+            transmission_factor: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+//テクスチャの値に係数を掛ける
+struct Scaled {
+    texture: Arc<dyn Texture>,
+    factor: Color,
+}
+
+impl Texture for Scaled {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        self.texture.value(u, v, p).mult(self.factor)
+    }
+}
+
+#[allow(dead_code)]
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(base_color)))
+    }
+    pub fn textured(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic_roughness: Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0))),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            sheen_color: None,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+    pub fn with_metallic(mut self, metallic: f64) -> Self {
+        self.metallic = metallic;
+        self
+    }
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness;
+        self
+    }
+    pub fn with_specular(mut self, specular: f64) -> Self {
+        self.specular = specular;
+        self
+    }
+    pub fn with_clearcoat(mut self, clearcoat: f64, roughness: f64) -> Self {
+        self.clearcoat = clearcoat;
+        self.clearcoat_roughness = roughness;
+        self
+    }
+    pub fn with_sheen(mut self, sheen: f64, tint: f64) -> Self {
+        self.sheen = sheen;
+        self.sheen_tint = tint;
+        self
+    }
+    pub fn with_transmission(mut self, transmission: f64, ior: f64) -> Self {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+    pub fn from_gltf(pbr: &GltfPbr) -> Self {
+        let base_color: Arc<dyn Texture> = match &pbr.base_color_texture {
+            Some(t) => Arc::new(Scaled { texture: Arc::clone(t), factor: pbr.base_color_factor }),
+            None => Arc::new(SolidColor::new(pbr.base_color_factor)),
+        };
+        let mut m = Self::textured(base_color)
+            .with_metallic(pbr.metallic_factor)
+            .with_roughness(pbr.roughness_factor)
+            .with_clearcoat(pbr.clearcoat_factor, pbr.clearcoat_roughness_factor)
+            .with_transmission(pbr.transmission_factor, pbr.ior);
+        if let Some(t) = &pbr.metallic_roughness_texture {
+            m.metallic_roughness = Arc::clone(t);
+        }
+        //glTFのF0はiorから決まる、ior=1.5でspecular=0.5
+        let f0 = ((pbr.ior - 1.0) / (pbr.ior + 1.0)).powi(2);
+        m.specular = f0 / 0.08;
+        //シーンの色は最大成分を強さ、それで割った色を色味として扱う
+        let c = pbr.sheen_color_factor;
+        let sheen = c.x.max(c.y).max(c.z);
+        if sheen > 0.0 {
+            m.sheen = sheen;
+            m.sheen_color = Some(c / sheen);
+        }
+        m
+    }
+    //粗いガラスとしての反射・屈折、屈折したときだけtintを掛ける
+    fn transmit(&self, hit: &HitInfo, wo: Vec3, ggx: &Ggx, tint: Color) -> Option<ScatterInfo> {
+        let h = ggx.sample_vndf(wo);
+        let eta = if hit.front_face { self.ior } else { 1.0 / self.ior };
+        let f = fresnel_dielectric(wo.dot(&h), eta);
+        let (wi, weight) = match refract_microfacet(wo, h, eta) {
+            Some(t) if random() >= f => {
+                if t.z >= 0.0 {
+                    return None;
+                }
+                (t, tint)
+            }
+            _ => {
+                let r = h * (2.0 * wo.dot(&h)) - wo;
+                if r.z <= 0.0 {
+                    return None;
+                }
+                (r, Vec3::new(1.0, 1.0, 1.0))
+            }
+        };
+        Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), weight * (ggx.g(wo, wi) / ggx.g1(wo))))
+    }
+}
+
+fn schlick(f0: Color, cos: f64) -> Color {
+    let w = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * w
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+//拡散にsheenを重ねる、sheenの分だけベースを減らして反射率が1を超えないようにする
+fn with_sheen(base: Color, sheen: Color) -> Color {
+    let m = sheen.x.max(sheen.y).max(sheen.z);
+    let sheen = if m > 1.0 { sheen / m } else { sheen };
+    base * (1.0 - m.min(1.0)) + sheen
+}
+
+//マイクロファセット反射、Fは呼び出し側で掛ける
+fn sample_glossy(wo: Vec3, ggx: &Ggx) -> Option<(Vec3, Vec3, f64)> {
+    let h = ggx.sample_vndf(wo);
+    let wi = h * (2.0 * wo.dot(&h)) - wo;
+    if wi.z <= 0.0 {
+        return None;
+    }
+    Some((wi, h, ggx.g(wo, wi) / ggx.g1(wo)))
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let wo = hit.to_local(ray.d.norm() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let base = self.base_color.value(hit.u, hit.v, &hit.p);
+        let mr = self.metallic_roughness.value(hit.u, hit.v, &hit.p);
+        let metallic = (self.metallic * mr.z).clamp(0.0, 1.0);
+        let roughness = (self.roughness * mr.y).clamp(0.0, 1.0);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+
+        let ggx = Ggx::from_roughness(roughness, roughness);
+        //裏から当たるのは透過した光だけなので、ガラスとして抜ける
+        if !hit.front_face {
+            return self.transmit(hit, wo, &ggx, white);
+        }
+
+        //クリアコート、F0=0.04の誘電体の層
+        if self.clearcoat > 0.0 {
+            let fc = self.clearcoat * schlick(Vec3::new(0.04, 0.04, 0.04), wo.z).x;
+            if random() < fc {
+                let ggx = Ggx::from_roughness(self.clearcoat_roughness, self.clearcoat_roughness);
+                let (wi, _, g) = sample_glossy(wo, &ggx)?;
+                return Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), white * g));
+            }
+        }
+
+        //金属、F0がベースカラーになる
+        if random() < metallic {
+            let (wi, h, g) = sample_glossy(wo, &ggx)?;
+            let f = schlick(base, wo.dot(&h));
+            return Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), f * g));
+        }
+
+        //透過、入るときにベースカラーで色付く
+        if random() < self.transmission {
+            return self.transmit(hit, wo, &ggx, base);
+        }
+
+        //誘電体の鏡面反射、残りが拡散に回る
+        let f0 = (0.08 * self.specular).min(1.0);
+        let h = ggx.sample_vndf(wo);
+        let fs = schlick(Vec3::new(f0, f0, f0), wo.dot(&h)).x;
+        if random() < fs {
+            let wi = h * (2.0 * wo.dot(&h)) - wo;
+            if wi.z <= 0.0 {
+                return None;
+            }
+            return Some(ScatterInfo::new(hit.spawn_ray(hit.to_world(wi)), white * (ggx.g(wo, wi) / ggx.g1(wo))));
+        }
+
+        //拡散、cos分布でサンプリングするので重みはベースカラーのみ
+        let wi = Vec3::random_cosine_direction();
+        let mut albedo = base;
+        if self.sheen > 0.0 {
+            let sheen_color = self.sheen_color.unwrap_or_else(|| {
+                let lum = luminance(base);
+                let tint = if lum > 0.0 { base / lum } else { white };
+                white * (1.0 - self.sheen_tint) + tint * self.sheen_tint
+            });
+            let cos_d = wi.dot(&(wi + wo).norm());
+            albedo = with_sheen(base, sheen_color * (self.sheen * (1.0 - cos_d).powi(5)));
+        }
+        let d = hit.to_world(wi);
+        if d.dot(&hit.ng) <= 0.0 {
+            return None;
+        }
+        Some(ScatterInfo::new(hit.spawn_ray(d), albedo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheen_keeps_albedo_below_one() {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        for base in [white, Vec3::new(0.9, 0.1, 0.1), Vec3::zero()] {
+            for sheen in [Vec3::zero(), Vec3::new(0.3, 0.3, 0.3), Vec3::new(4.7, 0.0, 0.0), white * 2.0] {
+                let c = with_sheen(base, sheen);
+                assert!(c.x <= 1.0 + 1e-12 && c.y <= 1.0 + 1e-12 && c.z <= 1.0 + 1e-12, "{} + {} = {}", base, sheen, c);
+                assert!(c.x >= 0.0 && c.y >= 0.0 && c.z >= 0.0);
+            }
+        }
+        //sheenがなければベースのまま
+        let base = Vec3::new(0.2, 0.4, 0.6);
+        let c = with_sheen(base, Vec3::zero());
+        assert!((c - base).length() < 1e-24);
+    }
+}
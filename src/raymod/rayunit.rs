@@ -1,17 +1,29 @@
 ﻿use crate::raymod::*;
 
 use std::sync::Arc;
-use std::f64::consts::*;
+use std::f64::consts::PI;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Ray {
     pub o: Vec3,
     pub d: Vec3,
+    //シャッターが開いている間の時刻
+    pub time: f64,
+    //スペクトルモードで運ぶ波長[nm]、RGBモードでは0
+    pub wavelength: f64,
 }
 
 impl Ray {
+    #[allow(dead_code)]
     pub fn new(o: Vec3, d: Vec3) -> Ray {
-        Ray { o, d }
+        Ray::with_time(o, d, 0.0)
+    }
+    pub fn with_time(o: Vec3, d: Vec3, time: f64) -> Ray {
+        Ray { o, d, time, wavelength: 0.0 }
+    }
+    pub fn with_wavelength(mut self, wavelength: f64) -> Ray {
+        self.wavelength = wavelength;
+        self
     }
     pub fn at(&self,t:f64)->Vec3{
         self.o + self.d*t
@@ -26,6 +38,7 @@ pub enum Refl {
 }
 
 //左上が原点なPNGフォーマット対応
+#[derive(Clone)]
 pub struct Camera {
     pub origin: Vec3,
     pub upper_left_corner: Vec3,
@@ -35,6 +48,18 @@ pub struct Camera {
     pub u: Vec3,
     pub v: Vec3,
     pub w: Vec3,
+    //シャッターの開閉時刻
+    pub time0: f64,
+    pub time1: f64,
+    //ピントの合う距離
+    pub focus_dist: f64,
+    //画像に掛ける露出倍率
+    pub exposure: f64,
+    //絞りの形と口径食、色収差
+    pub aperture: Aperture,
+    pub cat_eye: f64,
+    pub lateral_ca: f64,
+    pub axial_ca: f64,
 }
 
 impl Camera {
@@ -45,7 +70,7 @@ impl Camera {
         let viewport_width = aspect_ratio * viewport_height;
 
         let w = (lookfrom - lookat).norm();
-        let u = ((vup % w)).norm();
+        let u = (vup % w).norm();
         let v = w % u;
 
         let origin = lookfrom;
@@ -63,35 +88,160 @@ impl Camera {
             u,
             v,
             w,
+            time0: 0.0,
+            time1: 0.0,
+            focus_dist,
+            exposure: 1.0,
+            aperture: Aperture::Disk,
+            cat_eye: 0.0,
+            lateral_ca: 0.0,
+            axial_ca: 0.0,
         }
     }
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = Vec3::random_in_unit_disk()*self.lens_radius;
+    pub fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_ray_at(s, t, 0.0)
+    }
+    //波長を持つレイ、色収差はここで掛かる
+    //口径食で遮られたらNone
+    pub fn get_ray_at(&self, s: f64, t: f64, wavelength: f64) -> Option<Ray> {
+        let rd = self.sample_lens(s, t)?*self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y; 
-        Ray::new(
+        let target = self.chromatic_target(self.upper_left_corner + self.horizontal * s -  self.vertical * t, wavelength);
+        Some(Ray::with_time(
             self.origin + offset ,
-			self.upper_left_corner + self.horizontal * s -  self.vertical * t - self.origin - offset,
-
-        )
+			target - self.origin - offset,
+            self.sample_time(),
+        ).with_wavelength(wavelength))
     }
 }
 
-pub struct HitInfo {
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct HitInfo<'a> {
     pub t: f64,
     pub p: Vec3,
+    //シェーディング法線、常にレイと向かい合う向き
     pub n: Vec3,
+    //幾何法線、レイのオフセットに使う
+    pub ng: Vec3,
+    //レイが物体の外側から当たった場合true
+    pub front_face: bool,
+    //レイの時刻と波長、散乱したレイに引き継ぐ
+    pub time: f64,
+    pub wavelength: f64,
+    pub u: f64,
+    pub v: f64,
+    //表面のパラメータ微分 dp/du, dp/dv
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    //シェーディング法線と直交する接線・従法線
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub m: Arc <dyn Material>,
+    pub shape: &'a dyn Shape,
+}
+
+#[allow(dead_code)]
+impl<'a> HitInfo<'a> {
+    //outward_normalは物体の外向き法線(正規化済み)
+    pub fn new(r:&Ray,t:f64,outward_normal:Vec3,m: Arc <dyn Material>,shape:&'a dyn Shape)->Self {
+        let front_face = r.d.dot(&outward_normal) < 0.0;
+        let n = if front_face { outward_normal } else { outward_normal*-1.0 };
+        let (tangent, bitangent) = n.orthonormal_basis();
+        Self{
+            t,
+            p:r.at(t),
+            n,
+            ng:n,
+            front_face,
+            time:r.time,
+            wavelength:r.wavelength,
+            u:0.0,
+            v:0.0,
+            dpdu:tangent,
+            dpdv:bitangent,
+            tangent,
+            bitangent,
+            m,
+            shape,
+        }
+    }
+    pub fn set_uv(&mut self,u:f64,v:f64) {
+        self.u = u;
+        self.v = v;
+    }
+    //パラメータ微分を設定し、接線をdp/du方向に揃える
+    pub fn set_derivatives(&mut self,dpdu:Vec3,dpdv:Vec3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.set_tangent(dpdu);
+    }
+    //接線方向を指定してシェーディング座標系を作り直す
+    pub fn set_tangent(&mut self,tangent:Vec3) {
+        let t = tangent - self.n*self.n.dot(&tangent);
+        if t.length() > EPS {
+            self.tangent = t.norm();
+            self.bitangent = self.n % self.tangent;
+        } else {
+            (self.tangent, self.bitangent) = self.n.orthonormal_basis();
+        }
+    }
+    //シェーディング法線だけを差し替える、幾何法線はそのまま
+    pub fn set_shading_normal(&mut self,n:Vec3) {
+        let n = n.norm();
+        self.n = if n.dot(&self.ng) < 0.0 { n*-1.0 } else { n };
+        self.set_tangent(self.tangent);
+    }
+    //幾何法線の側へ少しずらした位置から次のレイを出す
+    pub fn spawn_ray(&self,d:Vec3) -> Ray {
+        let offset = if d.dot(&self.ng) > 0.0 { self.ng*RAY_OFFSET } else { self.ng*-RAY_OFFSET };
+        Ray::with_time(self.p + offset, d, self.time).with_wavelength(self.wavelength)
+    }
+    //シェーディング座標系(tangent,bitangent,n)からワールド座標へ
+    pub fn to_world(&self,local:Vec3) -> Vec3 {
+        self.tangent*local.x + self.bitangent*local.y + self.n*local.z
+    }
+    pub fn to_local(&self,world:Vec3) -> Vec3 {
+        Vec3::new(world.dot(&self.tangent), world.dot(&self.bitangent), world.dot(&self.n))
+    }
 }
 
-impl HitInfo {
-    pub fn new(t:f64,p:Vec3,n:Vec3,m: Arc <dyn Material>)->Self {
-        Self{t,p,n,m}
+pub trait Shape: Sync + Send {
+    fn hit(&self, ray: &Ray, t0: f64, t1: f64) ->Option<HitInfo<'_>>;
+    //無限に広がる形状はNone
+    #[allow(dead_code)]
+    fn bounding_box(&self) -> Option<Aabb>;
+    //デバッグ出力用の名前、既定では型名
+    fn name(&self) -> &'static str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+    //t0から先の境界を近い順にすべて集める、CSGの子として使われる
+    fn boundaries(&self, r: &Ray, t0: f64) -> Vec<HitInfo<'_>> {
+        let mut out = Vec::new();
+        let mut t = t0;
+        while out.len() < MAX_EVENTS {
+            match self.hit(r, t, INF) {
+                Some(hit) => {
+                    t = hit.t + EPS * (1.0 + hit.t.abs());
+                    out.push(hit);
+                }
+                None => break,
+            }
+        }
+        out
     }
 }
 
-pub trait Shape: Sync {
-    fn hit(&self, ray: &Ray, t0: f64, t1: f64) ->Option<HitInfo>;
+//モジュールのパスと型引数を落とした型名
+pub fn short_type_name(name: &'static str) -> &'static str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
 }
 
 pub struct Sphere {
@@ -106,28 +256,95 @@ impl Sphere {
     }
 }
 
+//外向き法線から球面座標(u,v)とパラメータ微分を求める
+//uはy軸回りに-xから、vは-yから+yへ
+pub fn sphere_uv(n: &Vec3, radius: f64) -> (f64, f64, Vec3, Vec3) {
+    let theta = (-n.y).clamp(-1.0, 1.0).acos();
+    let phi = (-n.z).atan2(n.x) + PI;
+    let (sin_t, cos_t) = theta.sin_cos();
+    let (sin_p, cos_p) = phi.sin_cos();
+    let dpdu = Vec3::new(sin_p * sin_t, 0.0, cos_p * sin_t) * (2.0 * PI * radius);
+    let dpdv = Vec3::new(-cos_p * cos_t, sin_t, sin_p * cos_t) * (PI * radius);
+    (phi / (2.0 * PI), theta / PI, dpdu, dpdv)
+}
+
+//球との交点のうちt0..t1にある近い方のt
+fn sphere_hit(center: Vec3, radius: f64, r: &Ray, t0: f64, t1: f64) -> Option<f64> {
+    let oc = r.o - center;
+    let a = r.d.dot(&r.d);
+    let b = r.d.dot(&oc)*2.0;
+    let c = oc.dot(&oc) - radius * radius;
+    let d = b*b-4.0*a*c;
+
+    if d > 0.0 {
+        let root = d.sqrt();
+        let temp = (-b - root) / (2.0*a);
+        if temp < t1 && temp > t0 {
+            return Some(temp);
+        }
+        let temp = (-b + root) / (2.0*a);
+        if temp < t1 && temp > t0 {
+            return Some(temp);
+        }
+    }
+    None
+}
+
+fn sphere_hit_info<'a>(shape: &'a dyn Shape, center: Vec3, radius: f64, material: &Arc<dyn Material>, r: &Ray, t: f64) -> HitInfo<'a> {
+    let n = (r.at(t) - center) / radius;
+    let mut info = HitInfo::new(r, t, n, Arc::clone(material), shape);
+    let (u, v, dpdu, dpdv) = sphere_uv(&n, radius);
+    info.set_uv(u, v);
+    info.set_derivatives(dpdu, dpdv);
+    info
+}
+
 impl Shape for Sphere {
-    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo> {
-        let oc = r.o - self.center;
-        let a = r.d.dot(&r.d);
-        let b = r.d.dot(&oc)*2.0;
-        let c = oc.dot(&oc) - self.radius * self.radius;
-        let d = b*b-4.0*a*c;
-
-        if d > 0.0 {
-            let root = d.sqrt();
-            let temp = (-b - root) / (2.0*a);
-            if temp < t1 && temp > t0 {
-                let p=r.at(temp);
-                return Some(HitInfo::new(temp,p,(p-self.center)/self.radius, Arc::clone(&self.material)));
-            }
-            let temp = (-b + root) / (2.0*a);
-            if temp < t1 && temp > t0 {
-                let p=r.at(temp);
-                return Some(HitInfo::new(temp,p,(p-self.center)/self.radius,Arc::clone(&self.material)));
-            }
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let t = sphere_hit(self.center, self.radius, r, t0, t1)?;
+        Some(sphere_hit_info(self, self.center, self.radius, &self.material, r, t))
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let rv = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - rv, self.center + rv))
+    }
+}
+
+//time0からtime1の間にcenter0からcenter1へ直線的に動く球
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vec3, center1: Vec3, time0: f64, time1: f64, radius: f64, material: Arc<dyn Material>) -> Self {
+        Self { center0, center1, time0, time1, radius, material }
+    }
+    //時間幅が0なら動かない
+    pub fn center(&self, time: f64) -> Vec3 {
+        let span = self.time1 - self.time0;
+        if span == 0.0 {
+            return self.center0;
         }
-        None
+        self.center0 + (self.center1 - self.center0) * ((time - self.time0) / span)
+    }
+}
+
+impl Shape for MovingSphere {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let center = self.center(r.time);
+        let t = sphere_hit(center, self.radius, r, t0, t1)?;
+        Some(sphere_hit_info(self, center, self.radius, &self.material, r, t))
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let rv = Vec3::new(self.radius, self.radius, self.radius);
+        let b0 = Aabb::new(self.center0 - rv, self.center0 + rv);
+        let b1 = Aabb::new(self.center1 - rv, self.center1 + rv);
+        Some(b0.surrounding(&b1))
     }
 }
 
@@ -143,9 +360,16 @@ impl ShapeList {
         self.objects.push(object);
     }
     pub fn random_scene(&mut self){
-        self.push(Box::new(Sphere::new(
-            Vec3::new(0.0, -1000.0, 0.0),
-            1000.0,
+        self.random_spheres(false);
+    }
+    //拡散球がシャッター時間内に跳ねるモーションブラー版
+    pub fn bouncing_scene(&mut self){
+        self.random_spheres(true);
+    }
+    fn random_spheres(&mut self, bouncing: bool){
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
             Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
         )));
         for a in -11..11 {
@@ -156,11 +380,23 @@ impl ShapeList {
                     if choose_mat < 0.8 {
                         // diffuse
                         let albedo = Vec3::random().mult(Vec3::random() );
-                        self.push(Box::new(Sphere::new(
-                            center,
-                            0.2,
-                            Arc::new(Lambertian::new(albedo)),
-                        )));                    
+                        if bouncing {
+                            let center2 = center + Vec3::new(0.0, random_range(0.0, 0.5), 0.0);
+                            self.push(Box::new(MovingSphere::new(
+                                center,
+                                center2,
+                                0.0,
+                                1.0,
+                                0.2,
+                                Arc::new(Lambertian::new(albedo)),
+                            )));
+                        } else {
+                            self.push(Box::new(Sphere::new(
+                                center,
+                                0.2,
+                                Arc::new(Lambertian::new(albedo)),
+                            )));
+                        }
                     }else if choose_mat <0.95{
                         // Metal
                         let fuzz= random_range(0.0,0.5);
@@ -194,10 +430,368 @@ impl ShapeList {
             1.0,
             Arc::new(Metal::new(Vec3::new(0.7,0.6,0.5),0.0),))));
     }
+    //テクスチャの確認用
+    pub fn texture_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9), 2.0, 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+        self.push(Box::new(Sphere::new(
+            Vec3::new(0.0,1.0,0.0),
+            1.0,
+            Arc::new(Lambertian::textured(Arc::new(NoiseTexture::new(4.0, NoiseKind::Marble, Vec3::new(1.0,1.0,1.0))))),)));
+        let turb = Arc::new(NoiseTexture::new(2.0, NoiseKind::Turbulence, Vec3::new(0.8,0.5,0.3)));
+        self.push(Box::new(Sphere::new(
+            Vec3::new(-4.0,1.0,0.0),
+            1.0,
+            Arc::new(Bumped::new(
+                Arc::new(Lambertian::textured(turb.clone())),
+                Arc::new(BumpMap::new(turb, 0.3)))),)));
+        let fuzz = Arc::new(NoiseTexture::new(3.0, NoiseKind::Noise, Vec3::new(0.4,0.4,0.4)));
+        self.push(Box::new(Sphere::new(
+            Vec3::new(4.0,1.0,0.0),
+            1.0,
+            Arc::new(Metal::textured(Arc::new(SolidColor::new(Vec3::new(0.7,0.6,0.5))), fuzz)),)));
+    }
+
+    //UVマッピングの確認用、地球の画像がなければUV市松模様で代用する
+    pub fn earth_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9), 2.0, 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+        let uv_checker: Arc<dyn Texture> = Arc::new(UvChecker::from_colors(Vec3::new(0.8, 0.1, 0.1), Vec3::new(0.9, 0.9, 0.9), 16.0, 8.0));
+        let earth: Arc<dyn Texture> = match ImageTexture::load("earthmap.jpg", WrapMode::Repeat) {
+            Ok(tex) => Arc::new(tex),
+            Err(e) => {
+                eprintln!("earthmap.jpg: {}", e);
+                Arc::clone(&uv_checker)
+            }
+        };
+        let mut earth_mat: Arc<dyn Material> = Arc::new(Lambertian::textured(earth));
+        if let Ok(normal) = NormalMap::load("earthnormal.png", 1.0) {
+            earth_mat = Arc::new(Bumped::new(earth_mat, Arc::new(normal)));
+        }
+        self.push(Box::new(Sphere::new(
+            Vec3::new(0.0,1.0,0.0),
+            1.0,
+            earth_mat,)));
+        self.push(Box::new(Sphere::new(
+            Vec3::new(-4.0,1.0,0.0),
+            1.0,
+            Arc::new(Lambertian::textured(Arc::clone(&uv_checker))),)));
+        self.push(Box::new(Sphere::new(
+            Vec3::new(4.0,1.0,0.0),
+            1.0,
+            Arc::new(Metal::textured(uv_checker, Arc::new(SolidColor::new(Vec3::zero())))),)));
+    }
+    //前面と天井のないコーネルボックス風の部屋、光源は空
+    pub fn box_scene(&mut self){
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+        let green: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.2, 0.2, 0.2), Vec3::new(0.9, 0.9, 0.9), 8.0, 8.0));
+
+        self.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
+        self.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
+        self.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::new(Lambertian::textured(checker)))));
+        self.push(Box::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::clone(&white))));
+        let tall = Arc::new(BoxShape::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(165.0, 330.0, 165.0), Arc::clone(&white)));
+        self.push(Box::new(Instance::new(tall,
+            Transform::translate(Vec3::new(265.0, 0.0, 295.0)) * Transform::rotate_y(15.0))));
+        let short = Arc::new(BoxShape::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(165.0, 165.0, 165.0), white));
+        self.push(Box::new(Instance::new(short,
+            Transform::translate(Vec3::new(130.0, 0.0, 65.0)) * Transform::rotate_y(-18.0))));
+        //奥の壁に立てかけた鏡
+        self.push(Box::new(Quad::new(
+            Vec3::new(60.0, 0.0, 500.0),
+            Vec3::new(150.0, 0.0, -40.0),
+            Vec3::new(0.0, 300.0, 40.0),
+            Arc::new(Metal::new(Vec3::new(0.8, 0.85, 0.88), 0.0)))));
+    }
+    //コーネルボックス風の部屋に煙の箱と霧の球を置く
+    pub fn smoke_scene(&mut self){
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+        let green: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
+
+        self.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
+        self.push(Box::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
+        self.push(Box::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::clone(&white))));
+        self.push(Box::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::clone(&white))));
+
+        let tall: Arc<dyn Shape> = Arc::new(Instance::new(
+            Arc::new(BoxShape::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(165.0, 330.0, 165.0), Arc::clone(&white))),
+            Transform::translate(Vec3::new(265.0, 0.0, 295.0)) * Transform::rotate_y(15.0)));
+        let short: Arc<dyn Shape> = Arc::new(Instance::new(
+            Arc::new(BoxShape::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(165.0, 165.0, 165.0), Arc::clone(&white))),
+            Transform::translate(Vec3::new(130.0, 0.0, 65.0)) * Transform::rotate_y(-18.0)));
+        self.push(Box::new(ConstantMedium::new(tall, 0.01, Vec3::new(0.05, 0.05, 0.05))));
+        self.push(Box::new(ConstantMedium::new(short, 0.01, Vec3::new(1.0, 1.0, 1.0))));
+
+        //ガラス球の中に霧を閉じ込める
+        let glass: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::new(420.0, 100.0, 120.0), 90.0, Arc::new(Dielectric::new(1.5))));
+        self.push(Box::new(Sphere::new(Vec3::new(420.0, 100.0, 120.0), 90.0, Arc::new(Dielectric::new(1.5)))));
+        self.push(Box::new(ConstantMedium::new(glass, 0.02, Vec3::new(0.2, 0.4, 0.9))));
+    }
+    //平面の上に円柱・円錐・トーラス・円板を並べる
+    pub fn primitive_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9), 2.0, 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let stripes = Arc::new(Checker::from_colors(Vec3::new(0.8, 0.2, 0.1), Vec3::new(0.9, 0.8, 0.2), 4.0));
+        self.push(Box::new(Cylinder::new(
+            Vec3::new(0.0, 0.0, -2.5), up, 0.6, 1.5, true,
+            Arc::new(Lambertian::textured(stripes)))));
+        self.push(Box::new(Cone::new(
+            Vec3::new(0.0, 0.0, 0.0), up, 0.8, 1.8, true,
+            Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.1)))));
+        self.push(Box::new(Torus::new(
+            Vec3::new(0.0, 0.9, 2.5), Vec3::new(1.0, 0.3, 0.2), 0.7, 0.25,
+            Arc::new(Dielectric::new(1.5)))));
+        self.push(Box::new(Cylinder::new(
+            Vec3::new(-2.5, 0.0, 1.0), up, 0.5, 1.0, false,
+            Arc::new(Lambertian::new(Vec3::new(0.2, 0.4, 0.8))))));
+        self.push(Box::new(Disk::new(
+            Vec3::new(-3.0, 1.8, -1.0), Vec3::new(1.0, 0.2, 0.0), 0.8,
+            Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0)))));
+    }
+    //一つのトーラスを変換を変えて輪状に並べる
+    pub fn instance_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9), 2.0, 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+        let torus: Arc<dyn Shape> = Arc::new(Torus::new(
+            Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.5, 0.15,
+            Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.3), 0.05))));
+        let n = 12;
+        for i in 0..n {
+            let angle = 360.0 * i as f64 / n as f64;
+            let s = 0.6 + 0.4 * i as f64 / n as f64;
+            let transform = Transform::rotate_y(angle)
+                * Transform::translate(Vec3::new(2.5, 0.8, 0.0))
+                * Transform::rotate_x(30.0 * i as f64)
+                * Transform::scale(Vec3::new(s, s, 1.5 * s));
+            self.push(Box::new(Instance::new(Arc::clone(&torus), transform)));
+        }
+        //中央のトーラスはシャッター時間内に持ち上がる
+        let start = Transform::look_at(Vec3::new(0.0, 1.0, 0.0), Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0))
+            * Transform::rotate_x(90.0)
+            * Transform::scale(Vec3::new(1.5, 1.5, 1.5));
+        let end = Transform::translate(Vec3::new(0.0, 0.3, 0.0)) * start;
+        self.push(Box::new(Instance::moving(torus, start, end, 0.0, 1.0)));
+    }
+    //ボクセルの雲と発光する炎の玉、炎は透過率を積分して通り抜ける
+    pub fn cloud_scene(&mut self){
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.4, 0.45, 0.35))),
+        )));
+        let cloud = Arc::new(VoxelGrid::perlin_cloud(64, 4.0));
+        let bounds = Aabb::new(Vec3::new(-4.0, 1.0, -3.0), Vec3::new(2.0, 4.0, 3.0));
+        self.push(Box::new(GridMedium::new(cloud, bounds, 3.0, Vec3::new(0.95, 0.95, 0.95), 0.6)));
+
+        let fire = Arc::new(VoxelGrid::perlin_fire(48, 5.0));
+        let bounds = Aabb::new(Vec3::new(1.5, 0.2, 0.5), Vec3::new(3.5, 2.2, 2.5));
+        self.push(Box::new(GridMedium::new(fire, bounds, 4.0, Vec3::new(0.2, 0.2, 0.2), 0.0)
+            .with_tracking(Tracking::Ratio)
+            .with_emission_scale(2.0)));
+    }
+    //CSGで作ったレンズ・殻・サイコロ
+    pub fn csg_scene(&mut self){
+        let checker = Arc::new(Checker::from_colors(Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9), 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+
+        //2つの球の共通部分で凸レンズ
+        let glass: Arc<dyn Material> = Arc::new(Dielectric::new(1.5));
+        let lens = Intersection::new(
+            Arc::new(Sphere::new(Vec3::new(0.0, 1.2, -2.2), 1.5, Arc::clone(&glass))),
+            Arc::new(Sphere::new(Vec3::new(0.0, 1.2, 0.2), 1.5, glass)));
+        self.push(Box::new(Instance::new(Arc::new(lens),
+            Transform::translate(Vec3::new(0.0, 0.0, -2.0)) * Transform::rotate_y(60.0)
+                * Transform::translate(Vec3::new(0.0, 0.0, 1.0)))));
+
+        //球殻の上半分を切り取って中を見せる
+        let gold: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.2));
+        let shell = Difference::new(
+            Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::clone(&gold))),
+            Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.9, Arc::clone(&gold))));
+        let cut = Difference::new(Arc::new(shell),
+            Arc::new(BoxShape::new(Vec3::new(-1.5, 1.3, -1.5), Vec3::new(1.5, 2.5, 1.5), gold)));
+        self.push(Box::new(cut));
+        self.push(Box::new(Sphere::new(Vec3::new(0.0, 0.5, 0.0), 0.35, Arc::new(Lambertian::new(Vec3::new(0.1, 0.2, 0.7))))));
+
+        //立方体と球の共通部分から目の穴を引いたサイコロ
+        let ivory: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.9, 0.85, 0.75)));
+        let mut die: Arc<dyn Shape> = Arc::new(Intersection::new(
+            Arc::new(BoxShape::new(Vec3::new(-0.5, 0.0, -0.5), Vec3::new(0.5, 1.0, 0.5), Arc::clone(&ivory))),
+            Arc::new(Sphere::new(Vec3::new(0.0, 0.5, 0.0), 0.68, Arc::clone(&ivory)))));
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.7, 0.05, 0.05)));
+        for c in [Vec3::new(0.0, 0.5, 0.5), Vec3::new(0.5, 0.75, 0.2), Vec3::new(0.5, 0.25, -0.2), Vec3::new(0.0, 1.0, 0.0)] {
+            let pip: Arc<dyn Shape> = Arc::new(Sphere::new(c, 0.12, Arc::clone(&red)));
+            die = Arc::new(Difference::new(die, pip));
+        }
+        self.push(Box::new(Instance::new(die,
+            Transform::translate(Vec3::new(1.2, 0.0, 2.2)) * Transform::rotate_y(20.0))));
+
+        //和集合、球を貫く円柱
+        let blue: Arc<dyn Material> = Arc::new(Metal::new(Vec3::new(0.5, 0.6, 0.8), 0.05));
+        self.push(Box::new(Union::new(
+            Arc::new(Sphere::new(Vec3::new(0.5, 0.7, 3.6), 0.7, Arc::clone(&blue))),
+            Arc::new(Cylinder::new(Vec3::new(0.5, 0.0, 3.6), Vec3::new(0.0, 1.0, 0.0), 0.2, 1.9, true, blue)))));
+    }
+    //距離関数で作ったMandelbulb・ねじれた箱・溶け合う球・繰り返し
+    pub fn sdf_scene(&mut self){
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )));
+
+        let bulb = sd::translate(sd::mandelbulb(8.0, 10), Vec3::new(0.0, 1.2, 0.0));
+        self.push(Box::new(SdfShape::new(bulb,
+            Aabb::new(Vec3::new(-1.3, -0.1, -1.3), Vec3::new(1.3, 2.5, 1.3)),
+            Arc::new(Lambertian::new(Vec3::new(0.8, 0.5, 0.3))))));
+
+        let twisted = sd::translate(sd::round(sd::twist(sd::cuboid(Vec3::new(0.4, 1.0, 0.4)), 1.2), 0.05), Vec3::new(0.5, 1.05, 3.0));
+        self.push(Box::new(SdfShape::new(twisted,
+            Aabb::new(Vec3::new(-0.3, 0.0, 2.2), Vec3::new(1.3, 2.1, 3.8)),
+            Arc::new(Metal::new(Vec3::new(0.6, 0.7, 0.8), 0.05))).with_step_scale(0.5)));
+
+        let blob = sd::smooth_union(
+            sd::smooth_union(
+                sd::translate(sd::sphere(0.6), Vec3::new(0.5, 0.6, -3.0)),
+                sd::translate(sd::sphere(0.45), Vec3::new(0.5, 1.3, -2.7)), 0.4),
+            sd::translate(sd::sphere(0.35), Vec3::new(1.0, 0.35, -3.6)), 0.4);
+        self.push(Box::new(SdfShape::new(blob,
+            Aabb::new(Vec3::new(-0.5, -0.1, -4.3), Vec3::new(1.8, 2.0, -1.8)),
+            Arc::new(Dielectric::new(1.5)))));
+
+        //手前に並ぶ小さな角丸箱
+        let grid = sd::intersection(
+            sd::repeat(sd::round(sd::cuboid(Vec3::new(0.12, 0.12, 0.12)), 0.04), Vec3::new(0.5, 0.0, 0.5)),
+            sd::cuboid(Vec3::new(6.0, 0.2, 4.0)));
+        self.push(Box::new(SdfShape::new(sd::translate(grid, Vec3::new(4.0, 0.16, 0.0)),
+            Aabb::new(Vec3::new(2.9, -0.1, -4.1), Vec3::new(5.1, 0.5, 4.1)),
+            Arc::new(Lambertian::new(Vec3::new(0.2, 0.4, 0.7))))));
+    }
+    //材質の見本、奥の列から導体・誘電体・統合マテリアル・重ねたマテリアルと拡散面を並べる
+    pub fn material_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.1, 0.1, 0.1), Vec3::new(0.8, 0.8, 0.8), 2.0, 2.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+        let row = |z: f64, materials: Vec<Arc<dyn Material>>| -> Vec<Box<dyn Shape>> {
+            let n = materials.len() as f64;
+            materials.into_iter().enumerate().map(|(i, m)| {
+                let x = (i as f64 - (n - 1.0) / 2.0) * 1.2;
+                Box::new(Sphere::new(Vec3::new(x, 0.5, z), 0.5, m)) as Box<dyn Shape>
+            }).collect()
+        };
+        let conductors: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Conductor::gold(0.1)),
+            Arc::new(Conductor::copper(0.3)),
+            Arc::new(Conductor::aluminium(0.2)),
+            Arc::new(Conductor::silver(0.05)),
+            Arc::new(Conductor::anisotropic(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837), 0.05, 0.5)),
+        ];
+        let dielectrics: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Dielectric::new(1.5)),
+            Arc::new(Dielectric::tinted(1.5, Vec3::new(0.3, 0.8, 0.4), 1.0)),
+            Arc::new(RoughDielectric::new(1.5, 0.1)),
+            Arc::new(RoughDielectric::new(1.5, 0.3)
+                .with_absorption(absorption_from_color(Vec3::new(0.3, 0.5, 0.9), 1.0))),
+            Arc::new(Dielectric::tinted(2.4, Vec3::new(0.9, 0.7, 0.3), 0.5)),
+        ];
+        let principled: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Principled::new(Vec3::new(0.8, 0.1, 0.1)).with_roughness(0.3)),
+            Arc::new(Principled::new(Vec3::new(0.95, 0.75, 0.4)).with_metallic(1.0).with_roughness(0.4)),
+            Arc::new(Principled::new(Vec3::new(0.05, 0.15, 0.6)).with_metallic(0.4).with_clearcoat(1.0, 0.03)),
+            Arc::new(Principled::new(Vec3::new(0.3, 0.02, 0.05)).with_roughness(0.9).with_sheen(1.0, 0.5)),
+            Arc::new(Principled::from_gltf(&GltfPbr {
+                base_color_factor: Vec3::new(0.7, 0.95, 0.8),
+                metallic_factor: 0.0,
+                roughness_factor: 0.1,
+                transmission_factor: 1.0,
+                ..Default::default()
+            })),
+        ];
+        let wood: Arc<dyn Material> = Arc::new(Lambertian::textured(
+            Arc::new(NoiseTexture::new(6.0, NoiseKind::Marble, Vec3::new(0.55, 0.3, 0.12)))));
+        let spots = Arc::new(Checker::from_colors(Vec3::zero(), Vec3::new(1.0, 1.0, 1.0), 6.0));
+        let layered: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Coated::new(wood, 1.5, 0.02).with_color(Vec3::new(0.95, 0.85, 0.6))),
+            Arc::new(Coated::new(Arc::new(Conductor::copper(0.4)), 1.5, 0.0)),
+            Arc::new(MixMaterial::textured(
+                Arc::new(Lambertian::new(Vec3::new(0.1, 0.3, 0.1))),
+                Arc::new(Conductor::silver(0.1)),
+                spots)),
+            Arc::new(MixMaterial::new(
+                Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.75))),
+                Arc::new(Conductor::aluminium(0.3)), 0.3)),
+            Arc::new(OrenNayar::new(Vec3::new(0.75, 0.45, 0.3), 0.6)),
+            Arc::new(Translucent::new(Vec3::new(0.15, 0.35, 0.05), Vec3::new(0.25, 0.5, 0.05))),
+        ];
+        let rows = [row(-1.4, conductors), row(0.0, dielectrics), row(1.4, principled), row(2.8, layered)];
+        for s in rows.into_iter().flatten() {
+            self.push(s);
+        }
+    }
+    //分散のあるガラス、--spectralで虹色の縁が出る
+    pub fn prism_scene(&mut self){
+        let checker = Arc::new(UvChecker::from_colors(Vec3::new(0.1, 0.1, 0.1), Vec3::new(0.9, 0.9, 0.9), 4.0, 4.0));
+        self.push(Box::new(Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::textured(checker)),
+        )));
+
+        //5枚の半空間の共通部分で三角柱を作る
+        let flint: Arc<dyn Material> = Arc::new(Dielectric::dispersive(Dispersion::dense_flint()));
+        let half_spaces = [
+            (Vec3::new(0.0, 0.02, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-0.9, 0.02, 0.0), Vec3::new(-0.866, 0.5, 0.0)),
+            (Vec3::new(0.9, 0.02, 0.0), Vec3::new(0.866, 0.5, 0.0)),
+            (Vec3::new(0.0, 0.0, 1.2), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.0, 0.0, -1.2), Vec3::new(0.0, 0.0, -1.0)),
+        ];
+        let mut prism: Option<Arc<dyn Shape>> = None;
+        for (p, n) in half_spaces {
+            let plane: Arc<dyn Shape> = Arc::new(Plane::new(p, n, Arc::clone(&flint)));
+            prism = Some(match prism {
+                Some(shape) => Arc::new(Intersection::new(shape, plane)),
+                None => plane,
+            });
+        }
+        if let Some(prism) = prism {
+            self.push(Box::new(Instance::new(prism, Transform::rotate_y(80.0))));
+        }
+
+        self.push(Box::new(Sphere::new(Vec3::new(-2.2, 0.7, 0.5), 0.7, Arc::new(Dielectric::dispersive(Dispersion::diamond())))));
+        self.push(Box::new(Sphere::new(Vec3::new(2.2, 0.7, 0.5), 0.7, Arc::new(Dielectric::dispersive(Dispersion::bk7())))));
+    }
 }
 
 impl Shape for ShapeList {
-    fn hit(&self, ray: &Ray, t0: f64, t1: f64) -> Option<HitInfo> {
+    fn hit(&self, ray: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
         let mut hit_info:Option<HitInfo> = None;
         let mut closest_so_far = t1;
         for object in &self.objects {
@@ -208,4 +802,15 @@ impl Shape for ShapeList {
         }
         hit_info
     }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut bbox: Option<Aabb> = None;
+        for object in &self.objects {
+            let b = object.bounding_box()?;
+            bbox = Some(match bbox {
+                Some(a) => a.surrounding(&b),
+                None => b,
+            });
+        }
+        bbox
+    }
 }
@@ -0,0 +1,201 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//平面の境界ボックスに与える厚み
+const PLANAR_PAD: f64 = 1e-4;
+
+//z=kの平面上の長方形、法線は+z
+pub struct XYRect {
+    pub x0: f64,
+    pub x1: f64,
+    pub y0: f64,
+    pub y1: f64,
+    pub k: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl XYRect {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { x0, x1, y0, y1, k, material }
+    }
+}
+
+impl Shape for XYRect {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let t = (self.k - r.o.z) / r.d.z;
+        if !(t > t0 && t < t1) {
+            return None;
+        }
+        let p = r.at(t);
+        if p.x < self.x0 || p.x > self.x1 || p.y < self.y0 || p.y > self.y1 {
+            return None;
+        }
+        let mut info = HitInfo::new(r, t, Vec3::new(0.0, 0.0, 1.0), Arc::clone(&self.material), self);
+        info.set_uv((p.x - self.x0) / (self.x1 - self.x0), (p.y - self.y0) / (self.y1 - self.y0));
+        info.set_derivatives(Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, self.y1 - self.y0, 0.0));
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::new(self.x0, self.y0, self.k), Vec3::new(self.x1, self.y1, self.k)).pad(PLANAR_PAD))
+    }
+}
+
+//y=kの平面上の長方形、法線は+y
+pub struct XZRect {
+    pub x0: f64,
+    pub x1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl XZRect {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { x0, x1, z0, z1, k, material }
+    }
+}
+
+impl Shape for XZRect {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let t = (self.k - r.o.y) / r.d.y;
+        if !(t > t0 && t < t1) {
+            return None;
+        }
+        let p = r.at(t);
+        if p.x < self.x0 || p.x > self.x1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+        let mut info = HitInfo::new(r, t, Vec3::new(0.0, 1.0, 0.0), Arc::clone(&self.material), self);
+        info.set_uv((p.x - self.x0) / (self.x1 - self.x0), (p.z - self.z0) / (self.z1 - self.z0));
+        info.set_derivatives(Vec3::new(self.x1 - self.x0, 0.0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::new(self.x0, self.k, self.z0), Vec3::new(self.x1, self.k, self.z1)).pad(PLANAR_PAD))
+    }
+}
+
+//x=kの平面上の長方形、法線は+x
+pub struct YZRect {
+    pub y0: f64,
+    pub y1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl YZRect {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { y0, y1, z0, z1, k, material }
+    }
+}
+
+impl Shape for YZRect {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let t = (self.k - r.o.x) / r.d.x;
+        if !(t > t0 && t < t1) {
+            return None;
+        }
+        let p = r.at(t);
+        if p.y < self.y0 || p.y > self.y1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+        let mut info = HitInfo::new(r, t, Vec3::new(1.0, 0.0, 0.0), Arc::clone(&self.material), self);
+        info.set_uv((p.y - self.y0) / (self.y1 - self.y0), (p.z - self.z0) / (self.z1 - self.z0));
+        info.set_derivatives(Vec3::new(0.0, self.y1 - self.y0, 0.0), Vec3::new(0.0, 0.0, self.z1 - self.z0));
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(Vec3::new(self.k, self.y0, self.z0), Vec3::new(self.k, self.y1, self.z1)).pad(PLANAR_PAD))
+    }
+}
+
+//角qと2辺u,vで張られる平行四辺形、法線はu x v方向
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u % v;
+        let normal = n.norm();
+        let d = normal.dot(&q);
+        let w = n / n.dot(&n);
+        Self { q, u, v, material, normal, d, w }
+    }
+}
+
+impl Shape for Quad {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let denom = self.normal.dot(&r.d);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(&r.o)) / denom;
+        if !(t > t0 && t < t1) {
+            return None;
+        }
+        //平行四辺形内の座標(alpha,beta)がそのままUVになる
+        let hp = r.at(t) - self.q;
+        let alpha = self.w.dot(&(hp % self.v));
+        let beta = self.w.dot(&(self.u % hp));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut info = HitInfo::new(r, t, self.normal, Arc::clone(&self.material), self);
+        info.set_uv(alpha, beta);
+        info.set_derivatives(self.u, self.v);
+        Some(info)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = Aabb::new(self.q, self.q + self.u + self.v).surrounding(&Aabb::new(self.q + self.u, self.q + self.v));
+        Some(b.pad(PLANAR_PAD))
+    }
+}
+
+//6枚のQuadからなる直方体
+pub struct BoxShape {
+    pub sides: ShapeList,
+    bbox: Aabb,
+}
+
+impl BoxShape {
+    pub fn new(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Self {
+        let bbox = Aabb::new(a, b);
+        let (min, max) = (bbox.min, bbox.max);
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        let mut sides = ShapeList::new();
+        //各面とも外向き法線になるよう辺の順序を選ぶ
+        sides.push(Box::new(Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, Arc::clone(&material)))); // front
+        sides.push(Box::new(Quad::new(Vec3::new(max.x, min.y, max.z), dz * -1.0, dy, Arc::clone(&material)))); // right
+        sides.push(Box::new(Quad::new(Vec3::new(max.x, min.y, min.z), dx * -1.0, dy, Arc::clone(&material)))); // back
+        sides.push(Box::new(Quad::new(Vec3::new(min.x, min.y, min.z), dz, dy, Arc::clone(&material)))); // left
+        sides.push(Box::new(Quad::new(Vec3::new(min.x, max.y, max.z), dx, dz * -1.0, Arc::clone(&material)))); // top
+        sides.push(Box::new(Quad::new(Vec3::new(min.x, min.y, min.z), dx, dz, material))); // bottom
+        Self { sides, bbox }
+    }
+}
+
+impl Shape for BoxShape {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        if !self.bbox.hit(r, t0, t1) {
+            return None;
+        }
+        self.sides.hit(r, t0, t1)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
@@ -0,0 +1,206 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//符号付き距離関数、内側で負になる
+pub trait Sdf: Sync + Send {
+    fn distance(&self, p: Vec3) -> f64;
+}
+
+impl<F: Fn(Vec3) -> f64 + Sync + Send> Sdf for F {
+    fn distance(&self, p: Vec3) -> f64 {
+        self(p)
+    }
+}
+
+pub type SdfRef = Arc<dyn Sdf>;
+
+fn abs3(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.abs(), v.y.abs(), v.z.abs())
+}
+
+fn max3(v: Vec3, m: f64) -> Vec3 {
+    Vec3::new(v.x.max(m), v.y.max(m), v.z.max(m))
+}
+
+fn len(v: Vec3) -> f64 {
+    v.length().sqrt()
+}
+
+//プリミティブとコンビネータ、どれもSdfRefを返すので自由に組み合わせられる
+#[allow(dead_code)]
+pub mod sd {
+    use super::*;
+
+    pub fn sphere(r: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| len(p) - r)
+    }
+    //bは各軸の半分の長さ
+    pub fn cuboid(b: Vec3) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let q = abs3(p) - b;
+            len(max3(q, 0.0)) + q.x.max(q.y.max(q.z)).min(0.0)
+        })
+    }
+    //xz平面上の主半径major、管の半径minorのトーラス
+    pub fn torus(major: f64, minor: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let q = (p.x * p.x + p.z * p.z).sqrt() - major;
+            (q * q + p.y * p.y).sqrt() - minor
+        })
+    }
+    pub fn capsule(a: Vec3, b: Vec3, r: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let pa = p - a;
+            let ba = b - a;
+            let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+            len(pa - ba * h) - r
+        })
+    }
+    //y軸方向の高さ2h、半径rの円柱
+    pub fn cylinder(r: f64, h: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let dx = (p.x * p.x + p.z * p.z).sqrt() - r;
+            let dy = p.y.abs() - h;
+            dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+        })
+    }
+    //法線nの平面、原点からの距離h
+    pub fn plane(n: Vec3, h: f64) -> SdfRef {
+        let n = n.norm();
+        Arc::new(move |p: Vec3| p.dot(&n) + h)
+    }
+    //power乗のMandelbulb、距離は推定値
+    pub fn mandelbulb(power: f64, iterations: usize) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let mut z = p;
+            let mut dr = 1.0;
+            let mut r = 0.0;
+            for _ in 0..iterations {
+                r = len(z);
+                if r > 2.0 {
+                    break;
+                }
+                let theta = (z.z / r).acos() * power;
+                let phi = z.y.atan2(z.x) * power;
+                dr = r.powf(power - 1.0) * power * dr + 1.0;
+                let zr = r.powf(power);
+                z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + p;
+            }
+            if r < EPS { 0.0 } else { 0.5 * r.ln() * r / dr }
+        })
+    }
+
+    pub fn union(a: SdfRef, b: SdfRef) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p).min(b.distance(p)))
+    }
+    pub fn intersection(a: SdfRef, b: SdfRef) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p).max(b.distance(p)))
+    }
+    pub fn difference(a: SdfRef, b: SdfRef) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p).max(-b.distance(p)))
+    }
+    //kは混ざる範囲の幅
+    pub fn smooth_union(a: SdfRef, b: SdfRef, k: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let (da, db) = (a.distance(p), b.distance(p));
+            let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+            db + (da - db) * h - k * h * (1.0 - h)
+        })
+    }
+    pub fn translate(a: SdfRef, d: Vec3) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p - d))
+    }
+    pub fn scale(a: SdfRef, s: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p / s) * s)
+    }
+    //角を半径rだけ丸める、形はrだけ太る
+    pub fn round(a: SdfRef, r: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| a.distance(p) - r)
+    }
+    //periodごとに無限に繰り返す、0の軸は繰り返さない
+    pub fn repeat(a: SdfRef, period: Vec3) -> SdfRef {
+        let wrap = |x: f64, c: f64| if c > 0.0 { x - c * (x / c).round() } else { x };
+        Arc::new(move |p: Vec3| a.distance(Vec3::new(wrap(p.x, period.x), wrap(p.y, period.y), wrap(p.z, period.z))))
+    }
+    //y軸回りに高さ1あたりk[rad]ねじる、距離が歪むのでSdfShapeのstep_scaleを下げること
+    pub fn twist(a: SdfRef, k: f64) -> SdfRef {
+        Arc::new(move |p: Vec3| {
+            let (s, c) = (k * p.y).sin_cos();
+            a.distance(Vec3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+        })
+    }
+}
+
+//レイマーチングの最大ステップ数
+const MAX_STEPS: usize = 512;
+
+//距離関数をスフィアトレーシングで描く形状、boundsの外は調べない
+pub struct SdfShape {
+    pub sdf: SdfRef,
+    pub bounds: Aabb,
+    pub material: Arc<dyn Material>,
+    //交差とみなす距離
+    pub hit_eps: f64,
+    //距離が過大評価される関数(twistなど)では1未満にする
+    pub step_scale: f64,
+}
+
+impl SdfShape {
+    pub fn new(sdf: SdfRef, bounds: Aabb, material: Arc<dyn Material>) -> Self {
+        Self { sdf, bounds, material, hit_eps: 1e-4, step_scale: 1.0 }
+    }
+    pub fn with_step_scale(mut self, step_scale: f64) -> Self {
+        self.step_scale = step_scale;
+        self
+    }
+    #[allow(dead_code)]
+    pub fn with_hit_eps(mut self, hit_eps: f64) -> Self {
+        self.hit_eps = hit_eps;
+        self
+    }
+    //中心差分による勾配
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let h = self.hit_eps * 0.5;
+        let d = |v: Vec3| self.sdf.distance(p + v) - self.sdf.distance(p - v);
+        Vec3::new(d(Vec3::new(h, 0.0, 0.0)), d(Vec3::new(0.0, h, 0.0)), d(Vec3::new(0.0, 0.0, h))).norm()
+    }
+}
+
+impl Shape for SdfShape {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let (ta, tb) = self.bounds.intersect(r, t0, t1)?;
+        let ray_length = len(r.d);
+        let mut t = ta;
+        //内側から出るレイは符号を反転して出口を探す
+        let d0 = self.sdf.distance(r.at(t));
+        let on_surface = d0.abs() < self.hit_eps;
+        //面上から出たレイは向かう側で符号を決め、面から十分離れるまで交差を無視する
+        let sign = if on_surface {
+            if self.normal(r.at(t)).dot(&r.d) > 0.0 { 1.0 } else { -1.0 }
+        } else {
+            d0.signum()
+        };
+        let mut escaped = !on_surface;
+        for _ in 0..MAX_STEPS {
+            if t >= tb {
+                return None;
+            }
+            let p = r.at(t);
+            let d = self.sdf.distance(p) * sign;
+            if d < self.hit_eps {
+                if escaped {
+                    return Some(HitInfo::new(r, t, self.normal(p), Arc::clone(&self.material), self));
+                }
+                t += self.hit_eps / ray_length;
+            } else {
+                escaped = true;
+                t += d * self.step_scale / ray_length;
+            }
+        }
+        None
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::raymod::*;
+
+use std::sync::OnceLock;
+
+//スペクトルモードで扱う波長の範囲[nm]
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+//波長は一様にサンプリングする
+pub fn sample_wavelength() -> f64 {
+    LAMBDA_MIN + random() * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+//区分ガウス関数、山の左右で幅が違う
+fn lobe(x: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+    (-0.5 * t * t).exp()
+}
+
+//CIE 1931等色関数の多峰ガウス近似 (Wyman, Sloan, Shirley 2013)
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+//XYZからリニアsRGB(D65)へ
+pub fn xyz_to_rgb(xyz: Vec3) -> Color {
+    Vec3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+//RGBを3つの滑らかな帯の和でスペクトルにする、帯の和は常に1なので白は平坦なスペクトルになる
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let smoothstep = |a: f64, b: f64, x: f64| {
+        let t = ((x - a) / (b - a)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let t1 = smoothstep(480.0, 510.0, lambda);
+    let t2 = smoothstep(570.0, 600.0, lambda);
+    rgb.z * (1.0 - t1) + rgb.y * (t1 - t2) + rgb.x * t2
+}
+
+//RGBモード(波長0)ならそのまま、スペクトルモードならその波長での値を3成分に並べる
+pub fn to_spectral(c: Color, wavelength: f64) -> Color {
+    if wavelength <= 0.0 {
+        return c;
+    }
+    let s = rgb_to_spectrum(c, wavelength);
+    Vec3::new(s, s, s)
+}
+
+//平坦なスペクトルが(1,1,1)になるようにする各チャンネルの係数
+fn white_balance() -> Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let mut xyz = Vec3::zero();
+        let mut lambda = LAMBDA_MIN;
+        while lambda < LAMBDA_MAX {
+            xyz = xyz + cie_xyz(lambda + 0.5);
+            lambda += 1.0;
+        }
+        let rgb = xyz_to_rgb(xyz / (LAMBDA_MAX - LAMBDA_MIN));
+        Vec3::new(1.0 / rgb.x, 1.0 / rgb.y, 1.0 / rgb.z)
+    })
+}
+
+//1波長の放射輝度をRGBの寄与に直す、一様サンプリングのpdfで割った値になる
+pub fn spectrum_to_rgb(value: f64, lambda: f64) -> Color {
+    xyz_to_rgb(cie_xyz(lambda) * value).mult(white_balance())
+}
+
+//波長依存の屈折率
+#[derive(Copy, Clone, Debug)]
+pub enum Dispersion {
+    //n = a + b / λ^2 (λはµm)
+    Cauchy { a: f64, b: f64 },
+    //n^2 = 1 + Σ B_i λ^2 / (λ^2 - C_i) (λはµm)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+#[allow(dead_code)]
+impl Dispersion {
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+    //SF11、分散の大きいフリントガラス
+    pub fn dense_flint() -> Self {
+        Dispersion::Sellmeier { b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629] }
+    }
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier { b: [0.3306, 4.3356, 0.0], c: [0.030625, 0.011236, 0.0] }
+    }
+    pub fn water() -> Self {
+        Dispersion::Cauchy { a: 1.3199, b: 0.00653 }
+    }
+    pub fn at(&self, lambda_nm: f64) -> f64 {
+        let l = lambda_nm * 1e-3;
+        let l2 = l * l;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt(),
+        }
+    }
+    //d線(587.6nm)での屈折率、RGBモードではこれを使う
+    pub fn nd(&self) -> f64 {
+        self.at(587.6)
+    }
+}
@@ -0,0 +1,298 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color;
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+        self.color
+    }
+}
+
+//空間座標による市松模様
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { even, odd, scale }
+    }
+    pub fn from_colors(even: Color, odd: Color, scale: f64) -> Self {
+        Self::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), scale)
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let k = (p.x * self.scale).floor() + (p.y * self.scale).floor() + (p.z * self.scale).floor();
+        if (k as i64) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+//UV座標による市松模様、nu x nvマスに分割
+pub struct UvChecker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub nu: f64,
+    pub nv: f64,
+}
+
+impl UvChecker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, nu: f64, nv: f64) -> Self {
+        Self { even, odd, nu, nv }
+    }
+    pub fn from_colors(even: Color, odd: Color, nu: f64, nv: f64) -> Self {
+        Self::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), nu, nv)
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let k = (u * self.nu).floor() + (v * self.nv).floor();
+        if (k as i64) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    //整数のテクセル座標を[0,n)に収める
+    fn texel(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n { m } else { 2 * n - 1 - m }
+            }
+        };
+        i as usize
+    }
+}
+
+//画像テクスチャ、画素はリニア空間に変換して保持する
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<Color>,
+    pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn load(filename: &str, wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Self::load_with(filename, wrap, to_linear)
+    }
+    //法線マップなど色ではないデータはガンマ補正せずに0..1で読む
+    pub fn load_data(filename: &str, wrap: WrapMode) -> Result<Self, image::ImageError> {
+        Self::load_with(filename, wrap, |c| c as f64 / 255.0)
+    }
+    fn load_with(filename: &str, wrap: WrapMode, decode: fn(u8) -> f64) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.to_rgb8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        //画素のない画像では折り返しも補間もできない
+        if width == 0 || height == 0 {
+            let kind = image::error::ParameterErrorKind::Generic(format!("{}: empty image", filename));
+            return Err(image::ImageError::Parameter(image::error::ParameterError::from_kind(kind)));
+        }
+        let data = img
+            .pixels()
+            .map(|px| Vec3::new(decode(px[0]), decode(px[1]), decode(px[2])))
+            .collect();
+        Ok(Self { width, height, data, wrap })
+    }
+    //画素がなければ黒
+    pub fn texel(&self, x: i64, y: i64) -> Color {
+        if self.data.is_empty() {
+            return Color::zero();
+        }
+        let x = self.wrap.texel(x, self.width);
+        let y = self.wrap.texel(y, self.height);
+        self.data[x + y * self.width]
+    }
+    //バイリニア補間、vは下から上へ
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let c00 = self.texel(x0, y0);
+        let c10 = self.texel(x0 + 1, y0);
+        let c01 = self.texel(x0, y0 + 1);
+        let c11 = self.texel(x0 + 1, y0 + 1);
+        (c00 * (1.0 - fx) + c10 * fx) * (1.0 - fy) + (c01 * (1.0 - fx) + c11 * fx) * fy
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Color {
+        self.sample(u, v)
+    }
+}
+
+fn to_linear(c: u8) -> f64 {
+    (c as f64 / 255.0).powf(2.2)
+}
+
+const POINT_COUNT: usize = 256;
+
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        let ranvec = (0..POINT_COUNT).map(|_| Vec3::vec3_random_range(-1.0, 1.0).norm()).collect();
+        Self {
+            ranvec,
+            perm_x: Self::generate_perm(),
+            perm_y: Self::generate_perm(),
+            perm_z: Self::generate_perm(),
+        }
+    }
+    fn generate_perm() -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = (random() * (i + 1) as f64) as usize;
+            p.swap(i, target.min(i));
+        }
+        p
+    }
+    pub fn noise(&self, p: &Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        //エルミート補間
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let idx = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let weight = Vec3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    accum += (di * uu + (1.0 - di) * (1.0 - uu))
+                        * (dj * vv + (1.0 - dj) * (1.0 - vv))
+                        * (dk * ww + (1.0 - dk) * (1.0 - ww))
+                        * self.ranvec[idx].dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+    pub fn turb(&self, p: &Vec3, depth: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p = temp_p * 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum NoiseKind {
+    Noise,
+    Turbulence,
+    Marble,
+}
+
+pub struct NoiseTexture {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub kind: NoiseKind,
+    pub color: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, kind: NoiseKind, color: Color) -> Self {
+        Self { perlin: Perlin::new(), scale, kind, color }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        let sp = *p * self.scale;
+        let n = match self.kind {
+            NoiseKind::Noise => 0.5 * (1.0 + self.perlin.noise(&sp)),
+            NoiseKind::Turbulence => self.perlin.turb(&sp, 7),
+            NoiseKind::Marble => 0.5 * (1.0 + (sp.z + 10.0 * self.perlin.turb(p, 7)).sin()),
+        };
+        self.color * n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_texture_is_black() {
+        let tex = ImageTexture { width: 0, height: 0, data: Vec::new(), wrap: WrapMode::Repeat };
+        let c = tex.sample(0.3, 0.7);
+        assert_eq!((c.x, c.y, c.z), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn empty_image_file_is_rejected() {
+        //0x0の画像はimageクレートでは書けないので、寸法0のPPMを直接作る
+        let path = std::env::temp_dir().join(format!("empty_texture_{}.ppm", std::process::id()));
+        std::fs::write(&path, b"P6\n0 0\n255\n").unwrap();
+        let result = ImageTexture::load(path.to_str().unwrap(), WrapMode::Clamp);
+        std::fs::remove_file(&path).ok();
+        assert!(result.err().unwrap().to_string().contains("empty image"));
+    }
+
+    #[test]
+    fn wrap_modes_stay_in_range() {
+        for i in -7..7 {
+            assert!(WrapMode::Repeat.texel(i, 3) < 3);
+            assert!(WrapMode::Clamp.texel(i, 3) < 3);
+            assert!(WrapMode::Mirror.texel(i, 3) < 3);
+        }
+        assert_eq!(WrapMode::Mirror.texel(3, 3), 2);
+        assert_eq!(WrapMode::Repeat.texel(-1, 3), 2);
+    }
+}
@@ -0,0 +1,531 @@
+use crate::raymod::*;
+
+use std::ops::Mul;
+use std::sync::Arc;
+
+//4x4行列、行優先
+#[derive(Copy, Clone, Debug)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+#[allow(dead_code)]
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = self.m[j][i];
+            }
+        }
+        Self { m }
+    }
+    //Gauss-Jordan法、特異行列やNaN・無限大を含む行列ならNone
+    pub fn inverse(&self) -> Option<Self> {
+        if self.m.iter().flatten().any(|e| !e.is_finite()) {
+            return None;
+        }
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+            for i in 0..4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0..4 {
+                        a[i][j] -= f * a[col][j];
+                        inv[i][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self { m: inv })
+    }
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 { Vec3::new(x, y, z) } else { Vec3::new(x, y, z) / w }
+    }
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+//アフィン変換、逆行列も合わせて持つ
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub m: Mat4,
+    pub inv: Mat4,
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub fn new(m: Mat4, inv: Mat4) -> Self {
+        Self { m, inv }
+    }
+    pub fn from_matrix(m: Mat4) -> Option<Self> {
+        Some(Self::new(m, m.inverse()?))
+    }
+    pub fn identity() -> Self {
+        Self::new(Mat4::identity(), Mat4::identity())
+    }
+    pub fn translate(d: Vec3) -> Self {
+        let m = Mat4::new([
+            [1.0, 0.0, 0.0, d.x],
+            [0.0, 1.0, 0.0, d.y],
+            [0.0, 0.0, 1.0, d.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inv = Mat4::new([
+            [1.0, 0.0, 0.0, -d.x],
+            [0.0, 1.0, 0.0, -d.y],
+            [0.0, 0.0, 1.0, -d.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Self::new(m, inv)
+    }
+    pub fn scale(s: Vec3) -> Self {
+        let m = Mat4::new([
+            [s.x, 0.0, 0.0, 0.0],
+            [0.0, s.y, 0.0, 0.0],
+            [0.0, 0.0, s.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inv = Mat4::new([
+            [1.0 / s.x, 0.0, 0.0, 0.0],
+            [0.0, 1.0 / s.y, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / s.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Self::new(m, inv)
+    }
+    //任意軸回りの回転、角度は度
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.norm();
+        let (sin_t, cos_t) = degrees.to_radians().sin_cos();
+        let m = Mat4::new([
+            [
+                a.x * a.x + (1.0 - a.x * a.x) * cos_t,
+                a.x * a.y * (1.0 - cos_t) - a.z * sin_t,
+                a.x * a.z * (1.0 - cos_t) + a.y * sin_t,
+                0.0,
+            ],
+            [
+                a.x * a.y * (1.0 - cos_t) + a.z * sin_t,
+                a.y * a.y + (1.0 - a.y * a.y) * cos_t,
+                a.y * a.z * (1.0 - cos_t) - a.x * sin_t,
+                0.0,
+            ],
+            [
+                a.x * a.z * (1.0 - cos_t) - a.y * sin_t,
+                a.y * a.z * (1.0 - cos_t) + a.x * sin_t,
+                a.z * a.z + (1.0 - a.z * a.z) * cos_t,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        //回転行列の逆は転置
+        Self::new(m, m.transpose())
+    }
+    pub fn rotate_x(degrees: f64) -> Self {
+        Self::rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+    pub fn rotate_y(degrees: f64) -> Self {
+        Self::rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+    pub fn rotate_z(degrees: f64) -> Self {
+        Self::rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+    //ローカルの原点をeyeに置き、+zがtarget、+yがupの側を向くように配置する
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let z = (target - eye).norm();
+        let x = (up % z).norm();
+        let y = z % x;
+        let m = Mat4::new([
+            [x.x, y.x, z.x, eye.x],
+            [x.y, y.y, z.y, eye.y],
+            [x.z, y.z, z.z, eye.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inv = Mat4::new([
+            [x.x, x.y, x.z, -x.dot(&eye)],
+            [y.x, y.y, y.z, -y.dot(&eye)],
+            [z.x, z.y, z.z, -z.dot(&eye)],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Self::new(m, inv)
+    }
+    pub fn inverse(&self) -> Self {
+        Self::new(self.inv, self.m)
+    }
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.m.point(p)
+    }
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        self.m.vector(v)
+    }
+    //法線は逆行列の転置で変換する
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        self.inv.transpose().vector(n)
+    }
+    //方向は正規化しないのでtはそのまま使える
+    pub fn ray(&self, r: &Ray) -> Ray {
+        Ray::with_time(self.point(r.o), self.vector(r.d), r.time).with_wavelength(r.wavelength)
+    }
+    pub fn aabb(&self, b: &Aabb) -> Aabb {
+        let mut out = Aabb::new(self.point(b.min), self.point(b.min));
+        for i in 0..8 {
+            let c = Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            );
+            let p = self.point(c);
+            out = out.surrounding(&Aabb::new(p, p));
+        }
+        out
+    }
+    //ローカル座標の交点情報をワールド座標に移す
+    pub fn hit(&self, hit: &mut HitInfo) {
+        hit.p = self.point(hit.p);
+        hit.n = self.normal(hit.n).norm();
+        hit.ng = self.normal(hit.ng).norm();
+        hit.dpdu = self.vector(hit.dpdu);
+        hit.dpdv = self.vector(hit.dpdv);
+        hit.set_tangent(self.vector(hit.tangent));
+    }
+}
+
+//a * b は b を適用してから a
+impl Mul for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Self) -> Self {
+        Transform::new(self.m * rhs.m, rhs.inv * self.inv)
+    }
+}
+
+//回転行列(左上3x3)から単位四元数(w, x, y, z)
+fn quat_from_matrix(m: &Mat4) -> [f64; 4] {
+    let m = &m.m;
+    let tr = m[0][0] + m[1][1] + m[2][2];
+    //対角成分の大きい所から求めて桁落ちを避ける
+    if tr > 0.0 {
+        let s = (tr + 1.0).sqrt() * 2.0;
+        [0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        [(m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s]
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        [(m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s]
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        [(m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s]
+    }
+}
+
+fn quat_to_matrix([w, x, y, z]: [f64; 4]) -> Mat4 {
+    Mat4::new([
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+fn quat_dot(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+//球面線形補間、短い方の弧を通る
+fn slerp(a: &[f64; 4], b: &[f64; 4], s: f64) -> [f64; 4] {
+    let d = quat_dot(a, b);
+    let (b, d) = if d < 0.0 { (b.map(|x| -x), -d) } else { (*b, d) };
+    let (wa, wb) = if d > 0.9995 {
+        //ほぼ同じ向きなら線形補間で十分
+        (1.0 - s, s)
+    } else {
+        let theta = d.acos();
+        (((1.0 - s) * theta).sin() / theta.sin(), (s * theta).sin() / theta.sin())
+    };
+    let q = [0, 1, 2, 3].map(|i| a[i] * wa + b[i] * wb);
+    let len = quat_dot(&q, &q).sqrt();
+    q.map(|x| x / len)
+}
+
+//極分解の反復回数の上限
+const POLAR_ITERATIONS: usize = 32;
+
+//平行移動・回転・伸縮に分けた変換、m = 平行移動 * 回転 * 伸縮
+#[derive(Copy, Clone, Debug)]
+struct Decomposed {
+    translate: Vec3,
+    rotate: [f64; 4],
+    //対称行列、せん断や鏡映もここに入る
+    stretch: Mat4,
+}
+
+impl Decomposed {
+    fn new(t: &Transform) -> Option<Self> {
+        let m = &t.m.m;
+        let translate = Vec3::new(m[0][3], m[1][3], m[2][3]);
+        let mut a = Mat4::identity();
+        for (row, src) in a.m.iter_mut().zip(m).take(3) {
+            row[..3].copy_from_slice(&src[..3]);
+        }
+        //鏡映を含むなら-1倍して回転を取り出し、符号は伸縮の側に残す
+        let det = a.m[0][0] * (a.m[1][1] * a.m[2][2] - a.m[1][2] * a.m[2][1]) - a.m[0][1] * (a.m[1][0] * a.m[2][2] - a.m[1][2] * a.m[2][0])
+            + a.m[0][2] * (a.m[1][0] * a.m[2][1] - a.m[1][1] * a.m[2][0]);
+        let mut r = a;
+        if det < 0.0 {
+            for row in r.m.iter_mut().take(3) {
+                for e in row.iter_mut().take(3) {
+                    *e = -*e;
+                }
+            }
+        }
+        //R = (R + R^-T) / 2 を繰り返すと直交行列に収束する
+        for _ in 0..POLAR_ITERATIONS {
+            let it = r.inverse()?.transpose();
+            let mut next = r;
+            let mut diff: f64 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    next.m[i][j] = (r.m[i][j] + it.m[i][j]) / 2.0;
+                    diff = diff.max((next.m[i][j] - r.m[i][j]).abs());
+                }
+            }
+            r = next;
+            if diff < 1e-12 {
+                break;
+            }
+        }
+        Some(Self { translate, rotate: quat_from_matrix(&r), stretch: r.transpose() * a })
+    }
+    fn lerp(&self, end: &Self, s: f64) -> Option<Transform> {
+        let mut stretch = self.stretch;
+        for i in 0..3 {
+            for j in 0..3 {
+                stretch.m[i][j] += (end.stretch.m[i][j] - self.stretch.m[i][j]) * s;
+            }
+        }
+        let mut m = quat_to_matrix(slerp(&self.rotate, &end.rotate, s)) * stretch;
+        let t = self.translate + (end.translate - self.translate) * s;
+        m.m[0][3] = t.x;
+        m.m[1][3] = t.y;
+        m.m[2][3] = t.z;
+        Transform::from_matrix(m)
+    }
+}
+
+//動くインスタンスの両端の変換
+pub struct Motion {
+    pub end: Transform,
+    pub time0: f64,
+    pub time1: f64,
+    start_key: Decomposed,
+    end_key: Decomposed,
+}
+
+//共有した形状を変換して配置する
+pub struct Instance {
+    pub shape: Arc<dyn Shape>,
+    pub transform: Transform,
+    //動く場合の終了時の変換、途中は平行移動・回転・伸縮に分けて補間する
+    pub motion: Option<Motion>,
+}
+
+//動く場合の境界ボックスを求める時刻の分割数
+const MOTION_STEPS: usize = 16;
+
+impl Instance {
+    pub fn new(shape: Arc<dyn Shape>, transform: Transform) -> Self {
+        Self { shape, transform, motion: None }
+    }
+    //回転は球面線形補間なので、大きく回っても途中で形は歪まない
+    //どちらかの端が特異な変換なら動かさない
+    pub fn moving(shape: Arc<dyn Shape>, start: Transform, end: Transform, time0: f64, time1: f64) -> Self {
+        let motion = Decomposed::new(&start)
+            .zip(Decomposed::new(&end))
+            .map(|(start_key, end_key)| Motion { end, time0, time1, start_key, end_key });
+        Self { shape, transform: start, motion }
+    }
+    //その時刻の変換、逆行列も補間した行列から解くので必ず互いの逆になる
+    //鏡映の有無が両端で違うと途中で特異になり、そのときはNone
+    pub fn transform_at(&self, time: f64) -> Option<Transform> {
+        match &self.motion {
+            None => Some(self.transform),
+            Some(motion) => {
+                let span = motion.time1 - motion.time0;
+                let s = if span == 0.0 { 0.0 } else { ((time - motion.time0) / span).clamp(0.0, 1.0) };
+                motion.start_key.lerp(&motion.end_key, s)
+            }
+        }
+    }
+}
+
+impl Shape for Instance {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        let transform = self.transform_at(r.time)?;
+        let local = transform.inverse().ray(r);
+        let mut info = self.shape.hit(&local, t0, t1)?;
+        transform.hit(&mut info);
+        Some(info)
+    }
+    //CSGの子になったときも中の形状の境界列をまとめて変換する
+    fn boundaries(&self, r: &Ray, t0: f64) -> Vec<HitInfo<'_>> {
+        let Some(transform) = self.transform_at(r.time) else { return Vec::new() };
+        let local = transform.inverse().ray(r);
+        let mut out = self.shape.boundaries(&local, t0);
+        for info in out.iter_mut() {
+            transform.hit(info);
+        }
+        out
+    }
+    //動く場合は何時刻かの箱を合わせ、間で回転して膨らむ分を足す
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.shape.bounding_box()?;
+        let Some(motion) = &self.motion else { return Some(self.transform.aabb(&b)) };
+        let mut out = self.transform.aabb(&b).surrounding(&motion.end.aabb(&b));
+        for i in 1..MOTION_STEPS {
+            if let Some(t) = motion.start_key.lerp(&motion.end_key, i as f64 / MOTION_STEPS as f64) {
+                out = out.surrounding(&t.aabb(&b));
+            }
+        }
+        //1区間の回転角dと回転の中心からの距離rで、点は両端を結ぶ線分から2rdより離れない
+        let d = slerp_angle(&motion.start_key.rotate, &motion.end_key.rotate) / MOTION_STEPS as f64;
+        let mut radius: f64 = 0.0;
+        for i in 0..8 {
+            let c = Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            );
+            for key in [&motion.start_key, &motion.end_key] {
+                radius = radius.max(key.stretch.vector(c).length().sqrt());
+            }
+        }
+        let pad = Vec3::new(1.0, 1.0, 1.0) * (2.0 * radius * d);
+        Some(Aabb::new(out.min - pad, out.max + pad))
+    }
+}
+
+//2つの回転の間の角度
+fn slerp_angle(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    2.0 * quat_dot(a, b).abs().min(1.0).acos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &Mat4, b: &Mat4) -> bool {
+        a.m.iter().flatten().zip(b.m.iter().flatten()).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn inverse_of_affine_transform() {
+        let t = Transform::translate(Vec3::new(1.0, -2.0, 3.0)) * Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 37.0) * Transform::scale(Vec3::new(2.0, 0.5, 3.0));
+        let inv = t.m.inverse().unwrap();
+        assert!(close(&(t.m * inv), &Mat4::identity()));
+        assert!(close(&inv, &t.inv));
+    }
+
+    #[test]
+    fn inverse_needs_row_swap() {
+        let m = Mat4::new([[0.0, 1.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert!(close(&(m * m.inverse().unwrap()), &Mat4::identity()));
+    }
+
+    #[test]
+    fn inverse_of_singular_or_non_finite_is_none() {
+        assert!(Transform::scale(Vec3::new(1.0, 0.0, 1.0)).m.inverse().is_none());
+        let mut m = Mat4::identity();
+        m.m[1][2] = f64::NAN;
+        assert!(m.inverse().is_none());
+        m.m[1][2] = f64::INFINITY;
+        assert!(m.inverse().is_none());
+    }
+
+    #[test]
+    fn moving_translation_interpolates_inverse() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        let end = Transform::translate(Vec3::new(4.0, 0.0, 0.0));
+        let inst = Instance::moving(sphere, Transform::identity(), end, 0.0, 1.0);
+        let mid = inst.transform_at(0.5).unwrap();
+        assert!(close(&(mid.m * mid.inv), &Mat4::identity()));
+        let r = Ray::with_time(Vec3::new(2.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+        let hit = inst.hit(&r, EPS, INF).unwrap();
+        assert!((hit.t - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn half_turn_stays_rigid() {
+        //180度回る間も行列と逆行列は互いの逆で、途中の姿勢は90度回転になる
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::new(3.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        let end = Transform::translate(Vec3::new(0.0, 2.0, 0.0)) * Transform::rotate_y(180.0) * Transform::scale(Vec3::new(2.0, 2.0, 2.0));
+        let inst = Instance::moving(sphere, Transform::identity(), end, 0.0, 1.0);
+        let mid = inst.transform_at(0.5).unwrap();
+        assert!(close(&(mid.m * mid.inv), &Mat4::identity()));
+        let expect = Transform::translate(Vec3::new(0.0, 1.0, 0.0)) * Transform::rotate_y(90.0) * Transform::scale(Vec3::new(1.5, 1.5, 1.5));
+        let flipped = Transform::translate(Vec3::new(0.0, 1.0, 0.0)) * Transform::rotate_y(-90.0) * Transform::scale(Vec3::new(1.5, 1.5, 1.5));
+        assert!(close(&mid.m, &expect.m) || close(&mid.m, &flipped.m));
+        //どの時刻の球も境界ボックスに入る
+        let b = inst.bounding_box().unwrap();
+        for i in 0..=100 {
+            let t = inst.transform_at(i as f64 / 100.0).unwrap();
+            let c = t.point(Vec3::new(3.0, 0.0, 0.0));
+            let radius = 1.0 + i as f64 / 100.0;
+            assert!(c.x - radius >= b.min.x && c.x + radius <= b.max.x, "{} {}", i, c);
+            assert!(c.z - radius >= b.min.z && c.z + radius <= b.max.z, "{} {}", i, c);
+        }
+    }
+
+    #[test]
+    fn decomposition_rebuilds_the_matrix() {
+        let t = Transform::translate(Vec3::new(1.0, -2.0, 3.0)) * Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 37.0) * Transform::scale(Vec3::new(2.0, -0.5, 3.0));
+        let key = Decomposed::new(&t).unwrap();
+        assert!(close(&key.lerp(&key, 0.3).unwrap().m, &t.m));
+    }
+}
@@ -1,8 +1,6 @@
 ﻿use std::ops::{Add, Sub, Mul, Div,Rem};
-use rand::prelude::*;
 use std::fs;
 use std::io::Write;
-use rayon::prelude::*;
 
 pub fn random() -> f64 {
     rand::random::<f64>()
@@ -21,6 +19,14 @@ pub struct Vec3 {
 
 pub type Color = Vec3;
 
+//デバッグ出力用、精度の指定がなければ小数3桁
+impl std::fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let p = f.precision().unwrap_or(3);
+        write!(f, "({:.*}, {:.*}, {:.*})", p, self.x, p, self.y, p, self.z)
+    }
+}
+
 #[allow(dead_code)]
 impl Vec3 {
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
@@ -34,26 +40,26 @@ impl Vec3 {
     }
     pub fn norm(mut self) -> Vec3 {
         let l = 1.0 / (self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
-        self.x = self.x * l;
-        self.y = self.y * l;
-        self.z = self.z * l;
+        self.x *= l;
+        self.y *= l;
+        self.z *= l;
         self
     }
     pub fn dot(&self, b: &Vec3) -> f64 {
-        return self.x * b.x + self.y * b.y + self.z * b.z;
+        self.x * b.x + self.y * b.y + self.z * b.z
     }
     pub fn length(&self)->f64{
-        return self.x*self.x+self.y*self.y+self.z*self.z;
+        self.x*self.x+self.y*self.y+self.z*self.z
     }
     pub fn random() -> Vec3{
-        return Vec3::new(random(),random(),random())
+        Vec3::new(random(),random(),random())
     }
     pub fn random_full() ->Vec3 {
         let x = random();
-        return Vec3::new(x,x,x)
+        Vec3::new(x,x,x)
     }
     pub fn vec3_random_range(a:f64,b:f64) ->Vec3 {
-        return Vec3::new(random_range(a,b),random_range(a,b),random_range(a,b))
+        Vec3::new(random_range(a,b),random_range(a,b),random_range(a,b))
     }
     pub fn random_hemisphere() -> Vec3 {
         loop {
@@ -63,6 +69,9 @@ impl Vec3 {
             }
         }
     }
+    pub fn random_unit_vector() -> Vec3 {
+        Vec3::random_hemisphere().norm()
+    }
     pub fn random_in_unit_disk() -> Vec3 {
     loop {
         let p = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
@@ -71,6 +80,21 @@ impl Vec3 {
         }
     }
 }
+    //z軸まわりのcos分布、単位円板の点を半球に持ち上げる
+    pub fn random_cosine_direction() -> Vec3 {
+        let d = Vec3::random_in_unit_disk();
+        Vec3::new(d.x, d.y, (1.0 - d.length()).max(0.0).sqrt())
+    }
+    //正規化済みベクトルを法線とする正規直交基底 (Duff et al. 2017)
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let sign = 1.0_f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
     pub fn reflect(&self,normal:Vec3) -> Vec3 {
         *self - normal*2.0*self.dot(&normal)
     }
@@ -79,7 +103,7 @@ impl Vec3 {
         let dt = uv.dot(&normal);
         let d = 1.0 - ni_over_nt*ni_over_nt * (1.0 - dt*dt);
         if d > 0.0 {
-            Some((uv - normal * dt)*(-1.0*ni_over_nt) - normal * d.sqrt())
+            Some((uv - normal * dt)*(-ni_over_nt) - normal * d.sqrt())
         } else {
             None
         }
@@ -128,13 +152,7 @@ impl Rem for Vec3 {
 
 
 fn clamp(x: f64) -> f64 {
-    if x < 0.0 {
-        0.0
-    } else if x > 1.0 {
-        1.0
-    } else {
-        x
-    }
+    x.clamp(0.0, 1.0)
 }
 
 
@@ -147,8 +165,8 @@ fn to_int(x: f64) -> u8 {
 fn save_ppm_file(filename: &str, image: Vec<Color>, width: usize, height: usize) {
     let mut f = fs::File::create(filename).unwrap();
     writeln!(f, "P3\n{} {}\n{}", width, height, 255).unwrap();
-    for i in 0..(width * (height)) {
-        write!(f, "{} {} {} ", to_int(image[i as usize].x), to_int(image[i as usize].y), to_int(image[i as usize].z)).unwrap();
+    for c in image.iter().take(width * height) {
+        write!(f, "{} {} {} ", to_int(c.x), to_int(c.y), to_int(c.z)).unwrap();
     }
 }
 
@@ -0,0 +1,479 @@
+use crate::raymod::*;
+
+use std::sync::Arc;
+
+//比率トラッキングで媒質内の面の手前に残す距離
+const SURFACE_GAP: f64 = 1e-4;
+
+//全方向に等しく散乱する位相関数
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        //媒質内の点なので表面のようにずらさない
+        let ray = Ray::with_time(hit.p, Vec3::random_unit_vector(), hit.time).with_wavelength(hit.wavelength);
+        Some(ScatterInfo::new(ray, self.albedo.value(hit.u, hit.v, &hit.p)))
+    }
+}
+
+//閉じた形状boundaryの内部を一様な密度で満たす煙・霧
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Shape>,
+    pub density: f64,
+    pub phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Shape>, density: f64, albedo: Color) -> Self {
+        Self::with_phase(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+    pub fn with_phase(boundary: Arc<dyn Shape>, density: f64, phase: Arc<dyn Material>) -> Self {
+        Self { boundary, density, phase }
+    }
+}
+
+impl Shape for ConstantMedium {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        //始点が内部にあっても入口が見つかるよう、直線全体で境界と交差させる
+        let enter = self.boundary.hit(r, -INF, INF)?.t;
+        let exit = self.boundary.hit(r, enter + RAY_OFFSET, INF)?.t;
+
+        let ta = enter.max(t0).max(0.0);
+        let tb = exit.min(t1);
+        if ta >= tb {
+            return None;
+        }
+        let ray_length = r.d.length().sqrt();
+        let distance_inside = (tb - ta) * ray_length;
+        let hit_distance = -(1.0 - random()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+        let t = ta + hit_distance / ray_length;
+        //法線は意味を持たないのでレイと向かい合う向きにしておく
+        Some(HitInfo::new(r, t, r.d.norm() * -1.0, Arc::clone(&self.phase), self))
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+//Henyey-Greenstein位相関数、g>0で前方散乱、g<0で後方散乱
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f64,
+    pub emission: Option<Arc<dyn Texture>>,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Self {
+        Self { albedo: Arc::new(SolidColor::new(albedo)), g, emission: None }
+    }
+    pub fn with_emission(mut self, emission: Arc<dyn Texture>) -> Self {
+        self.emission = Some(emission);
+        self
+    }
+    //進行方向となす角の余弦をサンプリングする
+    pub fn sample_cos(&self) -> f64 {
+        let xi = random();
+        if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let g = self.g;
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let w = ray.d.norm();
+        let (t, b) = w.orthonormal_basis();
+        let cos_t = self.sample_cos();
+        let sin_t = (1.0 - cos_t * cos_t).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * random();
+        let d = t * (sin_t * phi.cos()) + b * (sin_t * phi.sin()) + w * cos_t;
+        let scattered = Ray::with_time(hit.p, d, hit.time).with_wavelength(hit.wavelength);
+        Some(ScatterInfo::new(scattered, self.albedo.value(hit.u, hit.v, &hit.p)))
+    }
+    fn emitted(&self, hit: &HitInfo) -> Color {
+        match &self.emission {
+            Some(e) => e.value(hit.u, hit.v, &hit.p),
+            None => Color::zero(),
+        }
+    }
+}
+
+//密度と発光を持つボクセルグリッド、x方向が最も速く変わる順に並ぶ
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub density: Vec<f64>,
+    pub emission: Option<Vec<Color>>,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, density: Vec<f64>, emission: Option<Vec<Color>>) -> Self {
+        Self { nx, ny, nz, density, emission }
+    }
+    //ボクセル数、どれかの辺が0か桁あふれするならNone
+    fn voxel_count(nx: usize, ny: usize, nz: usize) -> Option<usize> {
+        if nx == 0 || ny == 0 || nz == 0 {
+            return None;
+        }
+        nx.checked_mul(ny)?.checked_mul(nz)
+    }
+    //8bitの生データ、0..255を0..1の密度とする
+    #[allow(dead_code)]
+    pub fn load_raw(filename: &str, nx: usize, ny: usize, nz: usize) -> std::io::Result<Self> {
+        let count = Self::voxel_count(nx, ny, nz)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid grid dimensions"))?;
+        let bytes = std::fs::read(filename)?;
+        if bytes.len() < count {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "voxel data too short"));
+        }
+        let density = bytes[..count].iter().map(|&b| b as f64 / 255.0).collect();
+        Ok(Self::new(nx, ny, nz, density, None))
+    }
+    //Mitsubaの.vol形式(version 3, float32)、1ch目を密度、4ch以上あれば2-4ch目を発光とする
+    #[allow(dead_code)]
+    pub fn load_vol(filename: &str) -> std::io::Result<Self> {
+        let bytes = std::fs::read(filename)?;
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid("not a version 3 .vol file"));
+        }
+        let int = |i: usize| i32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if int(4) != 1 {
+            return Err(invalid("only float32 .vol files are supported"));
+        }
+        let dims = [int(8), int(12), int(16), int(20)];
+        if dims.iter().any(|&d| d < 1) {
+            return Err(invalid("invalid grid dimensions"));
+        }
+        let [nx, ny, nz, ch] = dims.map(|d| d as usize);
+        let count = Self::voxel_count(nx, ny, nz).ok_or_else(|| invalid("invalid grid dimensions"))?;
+        let size = count.checked_mul(ch).and_then(|n| n.checked_mul(4)).and_then(|n| n.checked_add(48));
+        if size.is_none_or(|size| bytes.len() < size) {
+            return Err(invalid("voxel data too short"));
+        }
+        let float = |i: usize| {
+            let o = 48 + i * 4;
+            f32::from_le_bytes([bytes[o], bytes[o + 1], bytes[o + 2], bytes[o + 3]]) as f64
+        };
+        let density = (0..count).map(|i| float(i * ch)).collect();
+        let emission = if ch >= 4 {
+            Some((0..count).map(|i| Vec3::new(float(i * ch + 1), float(i * ch + 2), float(i * ch + 3))).collect())
+        } else {
+            None
+        };
+        Ok(Self::new(nx, ny, nz, density, emission))
+    }
+    //Perlinノイズで作る雲、中心から離れるほど薄くなる
+    pub fn perlin_cloud(n: usize, scale: f64) -> Self {
+        let perlin = Perlin::new();
+        let density = Self::generate(n, |p, r| {
+            let falloff = 1.0 - r / 0.5;
+            (falloff * 1.2 + perlin.turb(&(p * scale), 5) - 0.6).max(0.0)
+        });
+        Self::new(n, n, n, density, None)
+    }
+    //下ほど熱い炎の玉、温度に応じて黒体風の色で光る
+    pub fn perlin_fire(n: usize, scale: f64) -> Self {
+        let perlin = Perlin::new();
+        let mut emission = Vec::with_capacity(n * n * n);
+        let density = Self::generate(n, |p, r| {
+            let falloff = 1.0 - r / 0.5;
+            let d = (falloff + 0.8 * perlin.turb(&(p * scale), 5) - 0.5).max(0.0);
+            let heat = (d * (1.2 - p.y)).clamp(0.0, 1.0);
+            emission.push(Vec3::new(1.0, 0.35 * heat, 0.05 * heat * heat) * (heat * heat * 8.0));
+            d
+        });
+        Self::new(n, n, n, density, Some(emission))
+    }
+    //格子点の位置(0..1)と中心からの距離から値を作る
+    fn generate<F: FnMut(Vec3, f64) -> f64>(n: usize, mut f: F) -> Vec<f64> {
+        let mut out = Vec::with_capacity(n * n * n);
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    let p = Vec3::new(x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5) / n as f64;
+                    let r = (p - Vec3::new(0.5, 0.5, 0.5)).length().sqrt();
+                    out.push(f(p, r));
+                }
+            }
+        }
+        out
+    }
+    pub fn max_density(&self) -> f64 {
+        self.density.iter().cloned().fold(0.0, f64::max)
+    }
+    fn index(&self, x: i64, y: i64, z: i64) -> usize {
+        let x = x.clamp(0, self.nx as i64 - 1) as usize;
+        let y = y.clamp(0, self.ny as i64 - 1) as usize;
+        let z = z.clamp(0, self.nz as i64 - 1) as usize;
+        x + self.nx * (y + self.ny * z)
+    }
+    //0..1の局所座標で3線形補間する
+    fn lookup<T, F>(&self, p: Vec3, get: F) -> T
+    where
+        T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f64, Output = T>,
+        F: Fn(usize) -> T,
+    {
+        let x = p.x * self.nx as f64 - 0.5;
+        let y = p.y * self.ny as f64 - 0.5;
+        let z = p.z * self.nz as f64 - 0.5;
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+        let lerp = |a: T, b: T, t: f64| a * (1.0 - t) + b * t;
+        let c = |dx: i64, dy: i64, dz: i64| get(self.index(x0 + dx, y0 + dy, z0 + dz));
+        let c00 = lerp(c(0, 0, 0), c(1, 0, 0), fx);
+        let c10 = lerp(c(0, 1, 0), c(1, 1, 0), fx);
+        let c01 = lerp(c(0, 0, 1), c(1, 0, 1), fx);
+        let c11 = lerp(c(0, 1, 1), c(1, 1, 1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+    pub fn density_at(&self, p: Vec3) -> f64 {
+        self.lookup(p, |i| self.density[i])
+    }
+    pub fn emission_at(&self, p: Vec3) -> Color {
+        match &self.emission {
+            Some(e) => self.lookup(p, |i| e[i]),
+            None => Color::zero(),
+        }
+    }
+}
+
+//ボクセルグリッドをワールドの箱に割り当てたもの
+struct GridField {
+    grid: Arc<VoxelGrid>,
+    bounds: Aabb,
+}
+
+impl GridField {
+    fn local(&self, p: &Vec3) -> Vec3 {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        Vec3::new((p.x - min.x) / (max.x - min.x), (p.y - min.y) / (max.y - min.y), (p.z - min.z) / (max.z - min.z))
+    }
+}
+
+//ワールド座標で発光グリッドを引くテクスチャ
+pub struct GridEmission {
+    field: GridField,
+    pub scale: f64,
+}
+
+impl Texture for GridEmission {
+    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Color {
+        self.field.grid.emission_at(self.field.local(p)) * self.scale
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Tracking {
+    //実際の衝突点で位相関数により散乱させる
+    Delta,
+    //散乱させず透過率と発光を積分して通り抜ける、吸収・発光のみの炎向け
+    Ratio,
+}
+
+//非一様な密度の媒質、密度はgrid値 x density_scale
+pub struct GridMedium {
+    field: GridField,
+    pub density_scale: f64,
+    pub phase: Arc<dyn Material>,
+    pub emission_scale: f64,
+    pub tracking: Tracking,
+    majorant: f64,
+}
+
+impl GridMedium {
+    pub fn new(grid: Arc<VoxelGrid>, bounds: Aabb, density_scale: f64, albedo: Color, g: f64) -> Self {
+        let majorant = grid.max_density() * density_scale;
+        let field = GridField { grid, bounds };
+        let mut phase = HenyeyGreenstein::new(albedo, g);
+        if field.grid.emission.is_some() {
+            let emission = GridEmission { field: GridField { grid: Arc::clone(&field.grid), bounds }, scale: 1.0 };
+            phase = phase.with_emission(Arc::new(emission));
+        }
+        Self { field, density_scale, phase: Arc::new(phase), emission_scale: 1.0, tracking: Tracking::Delta, majorant }
+    }
+    pub fn with_tracking(mut self, tracking: Tracking) -> Self {
+        self.tracking = tracking;
+        self
+    }
+    pub fn with_emission_scale(mut self, scale: f64) -> Self {
+        self.emission_scale = scale;
+        self
+    }
+    fn sigma(&self, p: &Vec3) -> f64 {
+        self.field.grid.density_at(self.field.local(p)) * self.density_scale
+    }
+    //密度の上限を使って次の仮の衝突点までの距離を引く
+    fn step(&self, ray_length: f64) -> f64 {
+        -(1.0 - random()).ln() / (self.majorant * ray_length)
+    }
+}
+
+impl Shape for GridMedium {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64) -> Option<HitInfo<'_>> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (ta, tb) = self.field.bounds.intersect(r, t0.max(0.0), t1)?;
+        let ray_length = r.d.length().sqrt();
+        let dir = r.d.norm() * -1.0;
+        match self.tracking {
+            Tracking::Delta => {
+                let mut t = ta;
+                loop {
+                    t += self.step(ray_length);
+                    if t >= tb {
+                        return None;
+                    }
+                    if random() < self.sigma(&r.at(t)) / self.majorant {
+                        return Some(HitInfo::new(r, t, dir, Arc::clone(&self.phase), self));
+                    }
+                }
+            }
+            Tracking::Ratio => {
+                //媒質の中に面があれば(t1が出口より手前)その少し手前で積分を打ち切って抜け、面は続きのレイに当てる
+                let (_, exit) = self.field.bounds.intersect(r, t0.max(0.0), INF)?;
+                let end = if exit < t1 { exit } else { t1 - SURFACE_GAP / ray_length };
+                if end <= ta {
+                    return None;
+                }
+                let mut t = ta;
+                let mut transmittance = 1.0;
+                let mut emission = Color::zero();
+                loop {
+                    t += self.step(ray_length);
+                    if t >= end {
+                        break;
+                    }
+                    let p = r.at(t);
+                    let sigma = self.sigma(&p);
+                    let le = self.field.grid.emission_at(self.field.local(&p)) * self.emission_scale;
+                    emission = emission + le * (transmittance * sigma / self.majorant);
+                    transmittance *= 1.0 - sigma / self.majorant;
+                }
+                let m = Arc::new(PassThrough { transmittance, emission });
+                Some(HitInfo::new(r, end, dir, m, self))
+            }
+        }
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.field.bounds)
+    }
+}
+
+//媒質の出口で透過率を掛けてそのまま直進させる
+struct PassThrough {
+    transmittance: f64,
+    emission: Color,
+}
+
+impl Material for PassThrough {
+    fn scatter(&self, ray: &Ray, hit: &HitInfo) -> Option<ScatterInfo> {
+        let t = self.transmittance;
+        Some(ScatterInfo::new(Ray::with_time(hit.p, ray.d, hit.time).with_wavelength(hit.wavelength), Vec3::new(t, t, t)))
+    }
+    fn emitted(&self, _hit: &HitInfo) -> Color {
+        self.emission
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("raymod_{}_{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn vol_header(dims: [i32; 4]) -> Vec<u8> {
+        let mut b = b"VOL".to_vec();
+        b.push(3);
+        b.extend_from_slice(&1i32.to_le_bytes());
+        for d in dims {
+            b.extend_from_slice(&d.to_le_bytes());
+        }
+        b.resize(48, 0);
+        b
+    }
+
+    #[test]
+    fn load_raw_reads_density() {
+        let file = temp_file("ok.raw", &[0, 255, 51, 102, 0, 0, 0, 0]);
+        let grid = VoxelGrid::load_raw(&file, 2, 2, 2).unwrap();
+        assert_eq!(grid.density.len(), 8);
+        assert!((grid.density[1] - 1.0).abs() < 1e-12);
+        assert!((grid.density[2] - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn load_raw_rejects_bad_sizes() {
+        let file = temp_file("short.raw", &[0, 1, 2]);
+        assert!(VoxelGrid::load_raw(&file, 2, 2, 2).is_err());
+        assert!(VoxelGrid::load_raw(&file, 0, 1, 1).is_err());
+        assert!(VoxelGrid::load_raw(&file, usize::MAX, 2, 2).is_err());
+    }
+
+    #[test]
+    fn load_vol_reads_density_and_emission() {
+        let mut bytes = vol_header([1, 1, 2, 4]);
+        for v in [0.5f32, 1.0, 2.0, 3.0, 0.25, 0.0, 0.0, 0.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let grid = VoxelGrid::load_vol(&temp_file("ok.vol", &bytes)).unwrap();
+        assert_eq!((grid.nx, grid.ny, grid.nz), (1, 1, 2));
+        assert_eq!(grid.density, vec![0.5, 0.25]);
+        let e = grid.emission.unwrap();
+        assert_eq!((e[0].x, e[0].y, e[0].z), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn load_vol_rejects_bad_headers() {
+        for dims in [[0, 1, 1, 1], [1, -1, 1, 1], [1, 1, 1, 0], [i32::MAX, i32::MAX, i32::MAX, i32::MAX]] {
+            assert!(VoxelGrid::load_vol(&temp_file("bad.vol", &vol_header(dims))).is_err(), "{:?}", dims);
+        }
+        assert!(VoxelGrid::load_vol(&temp_file("magic.vol", b"NOTAVOLFILE")).is_err());
+    }
+
+    #[test]
+    fn density_is_trilinear() {
+        let grid = VoxelGrid::new(2, 1, 1, vec![0.0, 1.0], None);
+        assert!((grid.density_at(Vec3::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-12);
+        assert!((grid.density_at(Vec3::new(0.0, 0.5, 0.5))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ratio_tracking_stops_before_inner_surface() {
+        let grid = Arc::new(VoxelGrid::new(1, 1, 1, vec![1.0], None));
+        let bounds = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let medium = GridMedium::new(grid, bounds, 1.0, Vec3::new(1.0, 1.0, 1.0), 0.0).with_tracking(Tracking::Ratio);
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        //面がx=0にあるとき、媒質はその手前で抜ける
+        let hit = medium.hit(&r, EPS, 5.0).unwrap();
+        assert!(hit.t < 5.0 && hit.t > 4.0);
+        //面がなければ出口で抜ける
+        let hit = medium.hit(&r, EPS, INF).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-9);
+    }
+}